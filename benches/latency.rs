@@ -35,6 +35,9 @@ pub async fn spawn_app() -> Result<TestApp> {
         c.db.database = Uuid::new_v4().to_string();
         // Use a random OS port
         c.application.port = 0;
+        c.metrics.port = 0;
+        // we'd only be measuring how fast we say 429
+        c.rate_limit.enabled = false;
 
         c
     };
@@ -56,7 +59,7 @@ pub async fn spawn_app() -> Result<TestApp> {
     let address = format!("http://localhost:{}", port);
 
     // spawn our app as a background task
-    let _ = tokio::spawn(application.run_until_stopped());
+    drop(tokio::spawn(application.run_until_stopped()));
 
     // create our request client
     let api_client = reqwest::Client::builder()
@@ -111,9 +114,20 @@ async fn configure_db(settings: &DbSettings) -> Result<()> {
 }
 
 fn criterion_benchmark(c: &mut Criterion) {
-    // send requests to the latency endpoint
+    let rt = tokio::runtime::Runtime::new().expect("build tokio runtime");
+    let app = rt.block_on(spawn_app()).expect("spawn bench app");
+    let endpoint = format!("{}/latency", app.address);
 
-    c.bench_function("fib 20", |b| b.iter(|| fibonacci(black_box(20))));
+    // send requests to the latency endpoint
+    let mut group = c.benchmark_group("latency");
+    group.sample_size(10);
+    group.bench_function("get /latency", |b| {
+        b.iter(|| {
+            rt.block_on(app.api_client.get(black_box(&endpoint)).send())
+                .expect("send request")
+        })
+    });
+    group.finish();
 }
 
 criterion_group!(benches, criterion_benchmark);
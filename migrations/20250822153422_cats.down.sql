@@ -0,0 +1,3 @@
+-- uuid-ossp may be used by others, so it stays
+DROP TABLE cats;
+DROP TYPE eye_color;
@@ -0,0 +1,2 @@
+-- rows removed as duplicates don't come back
+ALTER TABLE cats DROP CONSTRAINT cats_pkey;
@@ -0,0 +1,7 @@
+-- earlier versions allowed duplicate ids, keep one arbitrary row for each
+DELETE FROM cats a
+USING cats b
+WHERE a.cool_cat_club_id = b.cool_cat_club_id
+  AND a.ctid > b.ctid;
+
+ALTER TABLE cats ADD PRIMARY KEY (cool_cat_club_id);
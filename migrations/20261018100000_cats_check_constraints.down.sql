@@ -0,0 +1,13 @@
+ALTER TABLE cats
+    DROP CONSTRAINT cats_name_length,
+    DROP CONSTRAINT cats_name_trimmed,
+    DROP CONSTRAINT cats_name_characters,
+    DROP CONSTRAINT cats_age_range,
+    DROP CONSTRAINT cats_id_not_nil;
+
+-- quarantined rows go back, names that were trimmed stay trimmed
+INSERT INTO cats (name, cool_cat_club_id, age, eye_color)
+SELECT name, cool_cat_club_id, age, eye_color FROM cats_quarantine
+ON CONFLICT (cool_cat_club_id) DO NOTHING;
+
+DROP TABLE cats_quarantine;
@@ -0,0 +1,33 @@
+-- earlier versions let anything in. Stray whitespace is fixed, any other row
+-- breaking the rules below is moved aside for someone to look at, rather than
+-- failing the migration and with it startup
+UPDATE cats SET name = btrim(name) WHERE name <> btrim(name);
+
+CREATE TABLE cats_quarantine (
+    name TEXT NOT NULL,
+    cool_cat_club_id UUID NOT NULL,
+    age SMALLINT NOT NULL,
+    eye_color eye_color NOT NULL,
+    quarantined_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+WITH invalid AS (
+    DELETE FROM cats
+    WHERE NOT (
+        char_length(name) BETWEEN 1 AND 64
+        AND name ~ '^[A-Za-z0-9 ''.-]+$'
+        AND age BETWEEN 0 AND 30
+        AND cool_cat_club_id <> '00000000-0000-0000-0000-000000000000'
+    )
+    RETURNING name, cool_cat_club_id, age, eye_color
+)
+INSERT INTO cats_quarantine (name, cool_cat_club_id, age, eye_color)
+SELECT name, cool_cat_club_id, age, eye_color FROM invalid;
+
+-- mirrors Cat::validate, keep the two in sync
+ALTER TABLE cats
+    ADD CONSTRAINT cats_name_length CHECK (char_length(name) BETWEEN 1 AND 64),
+    ADD CONSTRAINT cats_name_trimmed CHECK (name = btrim(name)),
+    ADD CONSTRAINT cats_name_characters CHECK (name ~ '^[A-Za-z0-9 ''.-]+$'),
+    ADD CONSTRAINT cats_age_range CHECK (age BETWEEN 0 AND 30),
+    ADD CONSTRAINT cats_id_not_nil CHECK (cool_cat_club_id <> '00000000-0000-0000-0000-000000000000');
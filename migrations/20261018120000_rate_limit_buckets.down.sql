@@ -0,0 +1,2 @@
+DROP FUNCTION rate_limit_take;
+DROP TABLE rate_limit_buckets;
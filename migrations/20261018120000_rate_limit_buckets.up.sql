@@ -0,0 +1,54 @@
+-- token buckets shared by every replica, losing them in a crash only resets
+-- the limits so there's no point paying for the wal
+CREATE UNLOGGED TABLE rate_limit_buckets (
+    key TEXT PRIMARY KEY,
+    tokens DOUBLE PRECISION NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL,
+    -- when the bucket is full again, after which the row can be swept
+    full_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX rate_limit_buckets_full_at ON rate_limit_buckets (full_at);
+
+-- refills the bucket for `bucket_key` and takes a token if there's one, in a
+-- single round trip so replicas can't both take the last token
+CREATE FUNCTION rate_limit_take(
+    bucket_key TEXT,
+    burst DOUBLE PRECISION,
+    per_second DOUBLE PRECISION,
+    OUT taken BOOLEAN,
+    OUT remaining DOUBLE PRECISION
+) LANGUAGE plpgsql AS $$
+DECLARE
+    stored_tokens DOUBLE PRECISION;
+    stored_at TIMESTAMPTZ;
+    at TIMESTAMPTZ;
+BEGIN
+    -- a client we haven't seen starts with a full bucket
+    INSERT INTO rate_limit_buckets (key, tokens, updated_at, full_at)
+    VALUES (bucket_key, burst, clock_timestamp(), clock_timestamp())
+    ON CONFLICT (key) DO NOTHING;
+
+    SELECT b.tokens, b.updated_at INTO stored_tokens, stored_at
+    FROM rate_limit_buckets b
+    WHERE b.key = bucket_key
+    FOR UPDATE;
+
+    -- read after the lock, now() could predate whoever held it
+    at := clock_timestamp();
+    remaining := least(
+        burst,
+        stored_tokens + greatest(0, extract(epoch FROM at - stored_at)) * per_second
+    );
+    taken := remaining >= 1;
+    IF taken THEN
+        remaining := remaining - 1;
+    END IF;
+
+    UPDATE rate_limit_buckets
+    SET tokens = remaining,
+        updated_at = at,
+        full_at = at + make_interval(secs => (burst - remaining) / per_second)
+    WHERE key = bucket_key;
+END
+$$;
@@ -0,0 +1,163 @@
+use crate::auth::Scope;
+use crate::cli::KeysCommand;
+use anyhow::{Context, bail};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+use sha2::{Digest, Sha256};
+use sqlx::{PgExecutor, PgPool};
+use tracing::info;
+use uuid::Uuid;
+
+/// Every key starts with this, so leaked ones are easy to grep for
+const KEY_PREFIX: &str = "ccc_";
+
+/// How much of a key is kept in the clear to tell keys apart
+const DISPLAY_PREFIX_LEN: usize = KEY_PREFIX.len() + 8;
+
+/// A key as stored, the key itself is never kept
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+}
+
+impl ApiKey {
+    /// Stores a new key with `scopes`, returning it along with the secret,
+    /// which is the only time the secret is available
+    pub async fn mint(db: &PgPool, name: &str, scopes: &[Scope]) -> sqlx::Result<(Self, String)> {
+        let mut secret = [0; 32];
+        StdRng::from_os_rng().fill_bytes(&mut secret);
+        let key = format!("{KEY_PREFIX}{}", URL_SAFE_NO_PAD.encode(secret));
+
+        let api_key = Self {
+            id: Uuid::now_v7(),
+            name: name.to_string(),
+            scopes: scopes.to_vec(),
+        };
+        sqlx::query(
+            "INSERT INTO api_keys (id, name, prefix, hash, scopes) VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(api_key.id)
+        .bind(&api_key.name)
+        .bind(&key[..DISPLAY_PREFIX_LEN])
+        .bind(hash(&key))
+        .bind(scope_names(scopes))
+        .execute(db)
+        .await?;
+
+        Ok((api_key, key))
+    }
+
+    /// The unrevoked key matching `key`, if there is one
+    pub async fn find_active(db: impl PgExecutor<'_>, key: &str) -> sqlx::Result<Option<Self>> {
+        // random keys can't be guessed from their hash, so no need for a slow one
+        let row: Option<(Uuid, String, Vec<String>)> = sqlx::query_as(
+            "SELECT id, name, scopes FROM api_keys WHERE hash = $1 AND revoked_at IS NULL",
+        )
+        .bind(hash(key))
+        .fetch_optional(db)
+        .await?;
+
+        Ok(row.map(|(id, name, scopes)| Self {
+            id,
+            name,
+            // scopes this build doesn't know about grant nothing
+            scopes: scopes.iter().filter_map(|s| s.parse().ok()).collect(),
+        }))
+    }
+
+    /// Revokes the key with `id`, false if there's no such unrevoked key
+    pub async fn revoke(db: &PgPool, id: Uuid) -> sqlx::Result<bool> {
+        let result = sqlx::query(
+            "UPDATE api_keys SET revoked_at = now() WHERE id = $1 AND revoked_at IS NULL",
+        )
+        .bind(id)
+        .execute(db)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+}
+
+/// Whether `token` looks like one of our keys, rather than say a JWT
+pub fn is_api_key(token: &str) -> bool {
+    token.starts_with(KEY_PREFIX)
+}
+
+pub(crate) fn hash(key: &str) -> Vec<u8> {
+    Sha256::digest(key.as_bytes()).to_vec()
+}
+
+fn scope_names(scopes: &[Scope]) -> Vec<String> {
+    scopes.iter().map(|s| s.to_string()).collect()
+}
+
+/// Runs a `gha_demo keys` subcommand against `db`
+pub async fn keys(command: KeysCommand, db: &PgPool) -> anyhow::Result<()> {
+    match command {
+        KeysCommand::Mint { name, scopes } => {
+            let (api_key, key) = ApiKey::mint(db, &name, &scopes)
+                .await
+                .context("store api key")?;
+            info!("minted api key {} named {name}", api_key.id);
+            // on a line of its own so scripts can pick it out
+            println!("{key}");
+            Ok(())
+        }
+        KeysCommand::List => list(db).await,
+        KeysCommand::Revoke { id } => {
+            if !ApiKey::revoke(db, id).await.context("revoke api key")? {
+                bail!("no unrevoked api key with id {id}");
+            }
+            info!("revoked api key {id}");
+            list(db).await
+        }
+    }
+}
+
+/// A key as `list` shows it
+#[derive(sqlx::FromRow)]
+struct KeyRow {
+    id: Uuid,
+    name: String,
+    prefix: String,
+    scopes: Vec<String>,
+    created_at: String,
+    revoked_at: Option<String>,
+}
+
+/// Prints one line per key, revoked ones included
+async fn list(db: &PgPool) -> anyhow::Result<()> {
+    let rows: Vec<KeyRow> = sqlx::query_as(
+        "SELECT id, name, prefix, scopes, created_at::text, revoked_at::text
+        FROM api_keys ORDER BY created_at, id",
+    )
+    .fetch_all(db)
+    .await
+    .context("list api keys")?;
+
+    println!(
+        "{:<38}{:<14}{:<10}{:<24}{:<32}NAME",
+        "ID", "PREFIX", "STATE", "SCOPES", "CREATED"
+    );
+    for row in rows {
+        let state = if row.revoked_at.is_some() {
+            "revoked"
+        } else {
+            "active"
+        };
+        println!(
+            "{:<38}{:<14}{state:<10}{:<24}{:<32}{}",
+            row.id.to_string(),
+            row.prefix,
+            row.scopes.join(","),
+            row.created_at,
+            row.name,
+        );
+    }
+
+    Ok(())
+}
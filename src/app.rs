@@ -1,92 +1,291 @@
-use crate::error::Result;
-use crate::routes::health::health;
+use crate::db::{Db, connect, startup_migrator};
+use crate::error::{Result, RunError, method_not_allowed, not_found};
+use crate::jwt::JwtValidator;
+use crate::metrics::{Metrics, metrics_handler, track_metrics};
+use crate::rate_limit::{RateLimiter, RouteGroup, rate_limit};
+use crate::request_id::request_id;
+use crate::routes::health::{health, live, ready};
 use crate::routes::latency::latency;
+use crate::routes::openapi::{docs, openapi_json};
 use crate::routes::v1::router::get_v1_router;
-use crate::settings::Settings;
+use crate::routes::v2::router::get_v2_router;
+use crate::server::serve;
+use crate::settings::{ApplicationSettings, AuthSettings, HttpSettings, Settings};
+use crate::shutdown::Shutdown;
+use crate::telemetry::{make_request_span, on_response};
+use crate::tls::Tls;
 use anyhow::Context;
 use axum::Router;
+use axum::middleware::{from_fn, from_fn_with_state};
 use axum::routing::get;
 use rand::SeedableRng;
 use rand::rngs::StdRng;
-use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::TcpListener;
-use tokio::signal::unix::{SignalKind, signal};
-use tracing::info;
+use tracing::{info, warn};
+
+/// How long closing the db pool may take once the servers are done
+const DB_CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
 
 pub struct App {
     router: axum::Router,
     listener: TcpListener,
+    tls: Option<Tls>,
+    http: HttpSettings,
+    metrics: Option<(axum::Router, TcpListener)>,
+    db: Db,
+    shutdown: Shutdown,
+    shutdown_delay: Duration,
+    drain_timeout: Duration,
 }
 
 #[derive(Clone, Debug)]
 pub struct AppState {
-    pub db: PgPool,
+    pub db: Db,
     pub rng: StdRng,
+    pub application: ApplicationSettings,
+    pub auth: AuthSettings,
+    pub jwt: Option<Arc<JwtValidator>>,
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+    pub shutdown: Shutdown,
 }
 
 impl App {
-    pub async fn build(settings: Settings) -> Result<Self> {
-        // app env changes what settings we're pulling
-        let mode = std::env::var("APP_ENV").unwrap_or("local".to_string());
-        info!("app mode: {mode}");
+    pub async fn build(settings: Settings) -> std::result::Result<Self, RunError> {
+        info!("app mode: {}", settings.environment);
 
         // create the DB connection with pool settings
-        let db = sqlx::pool::PoolOptions::new()
-            .connect_with(settings.db.get_db_settings())
+        let db = connect(&settings.db)
             .await
-            .with_context(|| format!("connect to db with settings: {:?}", settings.db))?;
+            .with_context(|| format!("connect to db with settings: {:?}", settings.db))
+            .map(Db::new)
+            .map_err(RunError::DbConnect)?;
 
-        // migrate the DB
-        info!("migrating the db...");
-        sqlx::migrate!("./migrations")
-            .run(&db)
-            .await
-            .context("migrate db")?;
+        // migrate the DB, unless that's left to `gha_demo migrate run`
+        if settings.db.migrate_on_start {
+            info!("migrating the db...");
+            startup_migrator()
+                .run(db.pool())
+                .await
+                .context("migrate db")
+                .map_err(RunError::Migrate)?;
+        } else {
+            info!("skipping migrations on start");
+        }
 
         // create the listener
         let listener = tokio::net::TcpListener::bind(settings.application.connection_string())
             .await
-            .context("create tcp listener")?;
+            .context("create tcp listener")
+            .map_err(RunError::Bind)?;
+
+        // a cert we can't load is as much a config problem as a bad setting
+        let tls = if settings.application.tls.enabled {
+            let tls = Tls::load(&settings.application.tls)
+                .context("load tls certificate")
+                .map_err(RunError::Config)?;
+            Some(tls)
+        } else {
+            None
+        };
+
+        // a key file we can't read is a config problem, an unreachable key
+        // server may well come back so we try again on the first token
+        let jwt = if settings.auth.jwt.enabled {
+            let jwt = JwtValidator::new(&settings.auth.jwt)
+                .context("set up jwt validation")
+                .map_err(RunError::Config)?;
+            if let Err(e) = jwt.refresh().await {
+                if settings.auth.jwt.jwks_path.is_some() {
+                    return Err(RunError::Config(e));
+                }
+                warn!("fetching jwks failed, will retry on demand: {e:?}");
+            }
+            Some(Arc::new(jwt))
+        } else {
+            None
+        };
+
+        // create the metrics, which are served on their own listener
+        let metrics = Metrics::new(db.clone()).map_err(|e| RunError::Telemetry(e.into()))?;
+        let metrics_server = if settings.metrics.enabled {
+            let listener = tokio::net::TcpListener::bind(settings.metrics.connection_string())
+                .await
+                .context("create metrics tcp listener")
+                .map_err(RunError::Bind)?;
+            let router = Router::new()
+                .route("/metrics", get(metrics_handler))
+                .with_state(metrics.clone());
+
+            Some((router, listener))
+        } else {
+            None
+        };
 
         // create rng
         let rng = StdRng::from_os_rng();
 
         // create our appstate
+        let shutdown = Shutdown::default();
         let app_state = AppState {
             db: db.clone(),
             rng,
+            application: settings.application.clone(),
+            auth: settings.auth.clone(),
+            jwt,
+            rate_limiter: settings
+                .rate_limit
+                .enabled
+                .then(|| Arc::new(RateLimiter::new(&settings.rate_limit, &db))),
+            shutdown: shutdown.clone(),
         };
+        let limited = |group| from_fn_with_state((app_state.clone(), group), rate_limit);
 
         // create the router
-        let router = Router::new()
+        let mut router = Router::new()
             .route("/health", get(health))
-            .route("/latency", get(latency))
-            .nest("/v1", get_v1_router())
-            .layer(tower_http::trace::TraceLayer::new_for_http())
+            .route("/health/live", get(live))
+            .route("/health/ready", get(ready))
+            .route(
+                "/latency",
+                get(latency).route_layer(limited(RouteGroup::Latency)),
+            )
+            .route("/openapi.json", get(openapi_json));
+
+        if settings.application.docs_ui {
+            router = router.route("/docs", get(docs));
+        }
+
+        let router = router
+            .nest(
+                "/v1",
+                get_v1_router().route_layer(limited(RouteGroup::Cats)),
+            )
+            .nest(
+                "/v2",
+                get_v2_router().route_layer(limited(RouteGroup::Cats)),
+            )
+            .fallback(not_found)
+            .method_not_allowed_fallback(method_not_allowed)
+            .layer(from_fn_with_state(metrics, track_metrics))
+            .layer(
+                tower_http::trace::TraceLayer::new_for_http()
+                    .make_span_with(make_request_span)
+                    .on_response(on_response),
+            )
+            .layer(from_fn(request_id))
             .with_state(app_state);
 
-        Ok(Self { listener, router })
+        Ok(Self {
+            listener,
+            tls,
+            http: settings.application.http.clone(),
+            router,
+            metrics: metrics_server,
+            db,
+            shutdown,
+            shutdown_delay: Duration::from_millis(settings.application.shutdown_delay_ms),
+            drain_timeout: Duration::from_millis(settings.application.drain_timeout_ms),
+        })
     }
 
     pub fn port(&self) -> Result<u16> {
         Ok(self.listener.local_addr().context("get local addr")?.port())
     }
 
+    /// Port the metrics listener is bound to, if metrics are enabled
+    pub fn metrics_port(&self) -> Result<Option<u16>> {
+        let Some((_, listener)) = &self.metrics else {
+            return Ok(None);
+        };
+
+        Ok(Some(
+            listener
+                .local_addr()
+                .context("get metrics local addr")?
+                .port(),
+        ))
+    }
+
+    /// Handle for stopping the app from the outside, it stops on SIGINT and
+    /// SIGTERM by itself
+    pub fn shutdown_handle(&self) -> Shutdown {
+        self.shutdown.clone()
+    }
+
+    /// Serves until shutdown is triggered. Readiness starts failing right away,
+    /// after `shutdown_delay` we stop accepting connections and in-flight
+    /// requests get `drain_timeout` to finish before being cut off.
     pub async fn run_until_stopped(self) -> Result<()> {
-        info!("starting server on {:?}", self.listener.local_addr());
-        axum::serve(self.listener, self.router)
-            .with_graceful_shutdown(wait_for_term())
+        self.shutdown.listen_for_signals()?;
+
+        // when to stop accepting new connections
+        let shutdown_delay = self.shutdown_delay;
+        let stop_accepting = |shutdown: Shutdown| async move {
+            shutdown.triggered().await;
+            info!("shutting down, accepting connections for another {shutdown_delay:?}");
+            tokio::time::sleep(shutdown_delay).await;
+            info!("no longer accepting connections");
+        };
+
+        let metrics = async {
+            let Some((router, listener)) = self.metrics else {
+                return Ok(());
+            };
+
+            info!("starting metrics server on {:?}", listener.local_addr());
+            serve(
+                listener,
+                router,
+                &self.http,
+                stop_accepting(self.shutdown.clone()),
+            )
+            .await;
+            Ok::<_, anyhow::Error>(())
+        };
+
+        let api = async {
+            info!("starting server on {:?}", self.listener.local_addr());
+            let stop = stop_accepting(self.shutdown.clone());
+            match self.tls {
+                Some(tls) => {
+                    info!("serving https");
+                    let listener = tls.listen(self.listener).context("listen for tls")?;
+                    serve(listener, self.router, &self.http, stop).await;
+                }
+                None => serve(self.listener, self.router, &self.http, stop).await,
+            }
+            Ok::<_, anyhow::Error>(())
+        };
+
+        let drain_deadline = async {
+            stop_accepting(self.shutdown.clone()).await;
+            tokio::time::sleep(self.drain_timeout).await;
+        };
+
+        // dropping the servers aborts their connections, so nothing is left
+        // using the pool once we close it
+        tokio::select! {
+            result = async { tokio::try_join!(api, metrics) } => {
+                result?;
+                info!("all connections drained");
+            }
+            _ = drain_deadline => {
+                warn!("connections still open after {:?}, closing them", self.drain_timeout);
+            }
+        }
+
+        // closing waits for connections to be handed back, which a stuck
+        // request never does
+        info!("closing db pool");
+        if tokio::time::timeout(DB_CLOSE_TIMEOUT, self.db.pool().close())
             .await
-            .context("runing api")?;
+            .is_err()
+        {
+            warn!("db pool didn't close within {DB_CLOSE_TIMEOUT:?}, leaving it");
+        }
 
         Ok(())
     }
 }
-
-async fn wait_for_term() {
-    signal(SignalKind::terminate())
-        .context("wait for SIGTERM")
-        .unwrap()
-        .recv()
-        .await;
-}
@@ -0,0 +1,170 @@
+//! Who's calling, and whether they're allowed to. Handlers opt in by taking an
+//! [`Authorized`] for the scope they need, which accepts our own api keys and,
+//! when configured, JWTs from the SSO.
+
+use crate::api_keys::{ApiKey, is_api_key};
+use crate::app::AppState;
+use crate::error::Error;
+use crate::jwt::TokenClaims;
+use axum::extract::FromRequestParts;
+use axum::http::HeaderMap;
+use axum::http::header::AUTHORIZATION;
+use axum::http::request::Parts;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scope {
+    #[serde(rename = "cats:read")]
+    CatsRead,
+    #[serde(rename = "cats:write")]
+    CatsWrite,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 2] = [Scope::CatsRead, Scope::CatsWrite];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::CatsRead => "cats:read",
+            Scope::CatsWrite => "cats:write",
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Scope::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == s)
+            .ok_or_else(|| format!("unknown scope {s}"))
+    }
+}
+
+/// The scope a route needs, as a type so it can go in an extractor
+pub trait RequiredScope {
+    const SCOPE: Scope;
+}
+
+pub struct CatsRead;
+
+impl RequiredScope for CatsRead {
+    const SCOPE: Scope = Scope::CatsRead;
+}
+
+pub struct CatsWrite;
+
+impl RequiredScope for CatsWrite {
+    const SCOPE: Scope = Scope::CatsWrite;
+}
+
+/// Whoever a bearer token turned out to belong to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Principal {
+    ApiKey(ApiKey),
+    Token(TokenClaims),
+}
+
+impl Principal {
+    pub fn scopes(&self) -> &[Scope] {
+        match self {
+            Principal::ApiKey(api_key) => &api_key.scopes,
+            Principal::Token(claims) => &claims.scopes,
+        }
+    }
+}
+
+/// Proof the caller holds an api key or JWT with scope `S`. Reads may be let
+/// through without one when `auth.public_reads` is set, in which case there's
+/// no principal to hand over.
+pub struct Authorized<S> {
+    pub principal: Option<Principal>,
+    scope: PhantomData<S>,
+}
+
+impl<S: RequiredScope> FromRequestParts<AppState> for Authorized<S> {
+    type Rejection = Error;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let Some(token) = bearer_token(&parts.headers)? else {
+            if S::SCOPE == Scope::CatsRead && state.auth.public_reads {
+                return Ok(Self {
+                    principal: None,
+                    scope: PhantomData,
+                });
+            }
+            return Err(Error::UnauthorizedError(
+                "a bearer token is required".to_string(),
+            ));
+        };
+
+        // our keys are easy to tell apart, anything else has to be a JWT
+        let principal = match &state.jwt {
+            Some(jwt) if !is_api_key(token) => Principal::Token(jwt.validate(token).await?),
+            // the rate limiter may have looked the key up already
+            _ => match parts.extensions.get::<ApiKey>() {
+                Some(api_key) => Principal::ApiKey(api_key.clone()),
+                None => {
+                    let mut conn = state.db.acquire().await?;
+                    Principal::ApiKey(ApiKey::find_active(&mut *conn, token).await?.ok_or_else(
+                        || {
+                            Error::UnauthorizedError(
+                                "the api key is invalid or revoked".to_string(),
+                            )
+                        },
+                    )?)
+                }
+            },
+        };
+
+        if !principal.scopes().contains(&S::SCOPE) {
+            let holder = match principal {
+                Principal::ApiKey(_) => "api key",
+                Principal::Token(_) => "token",
+            };
+            return Err(Error::ForbiddenError(format!(
+                "the {holder} lacks the {} scope",
+                S::SCOPE
+            )));
+        }
+
+        Ok(Self {
+            principal: Some(principal),
+            scope: PhantomData,
+        })
+    }
+}
+
+/// The token from an `Authorization: Bearer` header, if one was sent
+pub(crate) fn bearer_token(headers: &HeaderMap) -> Result<Option<&str>, Error> {
+    let Some(value) = headers.get(AUTHORIZATION) else {
+        return Ok(None);
+    };
+
+    // the scheme is case insensitive, the token isn't
+    let token = value
+        .to_str()
+        .ok()
+        .and_then(|v| v.split_once(' '))
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+        .map(|(_, token)| token.trim())
+        .filter(|token| !token.is_empty())
+        .ok_or_else(|| {
+            Error::UnauthorizedError("the authorization header must be a bearer token".to_string())
+        })?;
+
+    Ok(Some(token))
+}
@@ -0,0 +1,179 @@
+use crate::auth::Scope;
+use clap::{Arg, ArgAction, ArgMatches, Command, value_parser};
+use std::ffi::OsString;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+/// What the binary was asked to do, and where its settings come from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cli {
+    /// overrides the directory settings are read from
+    pub config_dir: Option<PathBuf>,
+    /// overrides `APP_ENV`
+    pub env: Option<String>,
+    pub command: CliCommand,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CliCommand {
+    /// Run the API, the default when no subcommand is given
+    Serve,
+    Migrate(MigrateCommand),
+    /// Print the merged settings and anything wrong with them
+    ConfigCheck,
+    Keys(KeysCommand),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MigrateCommand {
+    /// Show which migrations are applied and which are pending
+    Status,
+    /// Apply every pending migration
+    Run,
+    /// Revert applied migrations newer than `target`, 0 reverts everything
+    Revert { target: i64 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeysCommand {
+    /// Store a new api key and print it, the only time it's shown
+    Mint { name: String, scopes: Vec<Scope> },
+    /// Show every key, revoked ones included
+    List,
+    /// Stop accepting the key with `id`
+    Revoke { id: Uuid },
+}
+
+fn command() -> Command {
+    Command::new(env!("CARGO_PKG_NAME"))
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("The Cool Cat Club API")
+        .arg(
+            Arg::new("config-dir")
+                .long("config-dir")
+                .global(true)
+                .value_name("DIR")
+                .help("Directory holding base.yaml and the per environment files")
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("env")
+                .long("env")
+                .global(true)
+                .value_name("ENV")
+                .help("Environment whose settings to layer on top, overrides APP_ENV"),
+        )
+        .subcommand(Command::new("serve").about("Run the API (the default)"))
+        .subcommand(
+            Command::new("migrate")
+                .about("Manage database migrations")
+                .subcommand_required(true)
+                .subcommand(Command::new("status").about("Show applied and pending migrations"))
+                .subcommand(Command::new("run").about("Apply all pending migrations"))
+                .subcommand(
+                    Command::new("revert")
+                        .about("Revert applied migrations newer than a target version")
+                        .arg(
+                            Arg::new("target")
+                                .help("Version to revert to, 0 reverts everything")
+                                .required(true)
+                                .value_parser(value_parser!(i64).range(0..)),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("keys")
+                .about("Manage api keys")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("mint")
+                        .about("Create an api key and print it, it can't be shown again")
+                        .arg(
+                            Arg::new("name")
+                                .help("What the key is for, to tell keys apart")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::new("scope")
+                                .long("scope")
+                                .value_name("SCOPE")
+                                .help("Scope to grant, repeatable, defaults to every scope")
+                                .action(ArgAction::Append)
+                                .value_parser(|s: &str| s.parse::<Scope>()),
+                        ),
+                )
+                .subcommand(Command::new("list").about("Show every api key"))
+                .subcommand(
+                    Command::new("revoke").about("Revoke an api key").arg(
+                        Arg::new("id")
+                            .help("Id of the key, as shown by list")
+                            .required(true)
+                            .value_parser(|s: &str| s.parse::<Uuid>()),
+                    ),
+                ),
+        )
+        .subcommand(
+            Command::new("config")
+                .about("Inspect the settings")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("check")
+                        .about("Print the merged settings, secrets redacted, and validate them"),
+                ),
+        )
+}
+
+impl Cli {
+    pub fn try_parse_from<I, T>(args: I) -> Result<Self, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let matches = command().try_get_matches_from(args)?;
+        Ok(Self::from_matches(&matches))
+    }
+
+    fn from_matches(matches: &ArgMatches) -> Self {
+        // global args are readable from whichever subcommand was picked
+        let leaf = match matches.subcommand() {
+            Some((_, sub)) => sub.subcommand().map_or(sub, |(_, leaf)| leaf),
+            None => matches,
+        };
+
+        let command = match matches.subcommand() {
+            Some(("migrate", migrate)) => CliCommand::Migrate(match migrate.subcommand() {
+                Some(("status", _)) => MigrateCommand::Status,
+                Some(("run", _)) => MigrateCommand::Run,
+                Some(("revert", revert)) => MigrateCommand::Revert {
+                    target: *revert.get_one::<i64>("target").expect("target is required"),
+                },
+                _ => unreachable!("clap requires a known migrate subcommand"),
+            }),
+            Some(("keys", keys)) => CliCommand::Keys(match keys.subcommand() {
+                Some(("mint", mint)) => KeysCommand::Mint {
+                    name: mint
+                        .get_one::<String>("name")
+                        .expect("name is required")
+                        .clone(),
+                    scopes: match mint.get_many::<Scope>("scope") {
+                        Some(scopes) => scopes.copied().collect(),
+                        None => Scope::ALL.to_vec(),
+                    },
+                },
+                Some(("list", _)) => KeysCommand::List,
+                Some(("revoke", revoke)) => KeysCommand::Revoke {
+                    id: *revoke.get_one::<Uuid>("id").expect("id is required"),
+                },
+                _ => unreachable!("clap requires a known keys subcommand"),
+            }),
+            Some(("config", _)) => CliCommand::ConfigCheck,
+            _ => CliCommand::Serve,
+        };
+
+        Self {
+            config_dir: leaf.get_one::<PathBuf>("config-dir").cloned(),
+            env: leaf.get_one::<String>("env").cloned(),
+            command,
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use crate::settings::DbSettings;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use sqlx::migrate::Migrator;
+use sqlx::pool::PoolConnection;
+use sqlx::{PgPool, Postgres};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Every migration under `migrations/`, embedded at compile time
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// The pool, counting the tasks waiting on a connection since sqlx doesn't.
+/// Requests get their connections through [`Db::acquire`] so they're counted.
+#[derive(Debug, Clone)]
+pub struct Db {
+    pool: PgPool,
+    waiting: Arc<AtomicI64>,
+}
+
+/// Counts a task waiting in [`Db::acquire`] until it's dropped, so requests
+/// given up on while waiting don't count forever
+struct Waiting<'a>(&'a AtomicI64);
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Db {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            waiting: Arc::default(),
+        }
+    }
+
+    pub async fn acquire(&self) -> sqlx::Result<PoolConnection<Postgres>> {
+        self.waiting.fetch_add(1, Ordering::Relaxed);
+        let _waiting = Waiting(&self.waiting);
+        self.pool.acquire().await
+    }
+
+    /// How many tasks are waiting on a connection right now
+    pub fn waiting(&self) -> i64 {
+        self.waiting.load(Ordering::Relaxed)
+    }
+
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+}
+
+/// [`MIGRATOR`] for running on start. A newer release may have migrated the db
+/// ahead of us mid rollout, so migrations we don't know about are left alone
+/// rather than failing the start.
+pub fn startup_migrator() -> Migrator {
+    let mut migrator = sqlx::migrate!("./migrations");
+    migrator.set_ignore_missing(true);
+    migrator
+}
+
+/// Connects the pool, retrying with exponential backoff and jitter so we can
+/// start before postgres is up
+pub async fn connect(settings: &DbSettings) -> sqlx::Result<PgPool> {
+    let attempts = settings.connect_retries + 1;
+    let mut backoff = Duration::from_millis(settings.connect_backoff_initial_ms);
+    let max_backoff = Duration::from_millis(settings.connect_backoff_max_ms);
+    let mut rng = StdRng::from_os_rng();
+
+    let mut attempt = 1;
+    loop {
+        info!(attempt, attempts, "connecting to the db");
+        let err = match settings
+            .get_pool_options()
+            .connect_with(settings.get_db_settings())
+            .await
+        {
+            Ok(pool) => return Ok(pool),
+            Err(e) if attempt >= attempts => return Err(e),
+            Err(e) => e,
+        };
+
+        // anywhere between half and all of the backoff, so restarted replicas
+        // don't all hammer the db in lockstep
+        let half = backoff / 2;
+        let delay = half + half.mul_f64(rng.random());
+        warn!(attempt, attempts, ?delay, error = %err, "failed to connect to the db, retrying");
+        tokio::time::sleep(delay).await;
+
+        backoff = (backoff * 2).min(max_backoff);
+        attempt += 1;
+    }
+}
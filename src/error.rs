@@ -1,7 +1,15 @@
-use axum::http::StatusCode;
-use axum::{body::Body, http::Response, response::IntoResponse};
+use crate::request_id;
+use axum::extract::rejection::{JsonRejection, PathRejection, QueryRejection};
+use axum::http::header::{CONTENT_TYPE, RETRY_AFTER, WWW_AUTHENTICATE};
+use axum::http::{HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use std::process::ExitCode;
 use thiserror::Error;
 use tracing::error;
+use utoipa::ToSchema;
+
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
 
 #[derive(Error, Debug)]
 #[allow(clippy::enum_variant_names)]
@@ -9,36 +17,237 @@ pub enum Error {
     #[error("Unexpected Error")]
     UnexpectedError(#[from] anyhow::Error),
     #[error("Database Error")]
-    DbError(#[from] sqlx::Error),
+    DbError(sqlx::Error),
     #[error("Not Found")]
     NotFoundError,
+    #[error("Unauthorized: {0}")]
+    UnauthorizedError(String),
+    #[error("Forbidden: {0}")]
+    ForbiddenError(String),
+    #[error("Bad Request: {0}")]
+    BadRequestError(String),
+    #[error("Unprocessable Entity: {0}")]
+    UnprocessableEntityError(String),
+    #[error("Conflict: {0}")]
+    ConflictError(String),
+    /// the client is over its rate limit, and may retry in this many seconds
+    #[error("Too Many Requests, retry in {0}s")]
+    TooManyRequestsError(u64),
+    /// something we depend on is down, try again in this many seconds
+    #[error("Service Unavailable: {1}, retry in {0}s")]
+    ServiceUnavailableError(u64, String),
+    /// an extractor couldn't make sense of the request, axum picks the status
+    #[error("Rejected Request: {1}")]
+    RejectionError(StatusCode, String),
+    #[error("Validation Failed")]
+    ValidationError(Vec<FieldError>),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Why the process couldn't start or keep running. Each kind gets its own exit
+/// code (borrowed from sysexits.h) so orchestrators can tell them apart.
+#[derive(Error, Debug)]
+pub enum RunError {
+    #[error("invalid configuration")]
+    Config(#[source] anyhow::Error),
+    #[error("failed to set up telemetry")]
+    Telemetry(#[source] anyhow::Error),
+    #[error("failed to connect to the database")]
+    DbConnect(#[source] anyhow::Error),
+    #[error("failed to migrate the database")]
+    Migrate(#[source] anyhow::Error),
+    #[error("failed to bind a listener")]
+    Bind(#[source] anyhow::Error),
+    #[error("failed while serving")]
+    Serve(#[source] anyhow::Error),
+    #[error("failed to manage api keys")]
+    Keys(#[source] anyhow::Error),
+}
+
+impl RunError {
+    pub fn exit_code(&self) -> ExitCode {
+        match &self {
+            // EX_CONFIG
+            RunError::Config(_) => ExitCode::from(78),
+            // EX_SOFTWARE
+            RunError::Telemetry(_) => ExitCode::from(70),
+            // EX_UNAVAILABLE
+            RunError::DbConnect(_) => ExitCode::from(69),
+            // EX_DATAERR
+            RunError::Migrate(_) => ExitCode::from(65),
+            // EX_OSERR
+            RunError::Bind(_) => ExitCode::from(71),
+            RunError::Serve(_) => ExitCode::FAILURE,
+            RunError::Keys(_) => ExitCode::FAILURE,
+        }
+    }
+}
+
+/// RFC 7807 problem details, the body of every error response
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// every field that failed validation, only present for validation errors
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct FieldError {
+    pub field: String,
+    pub reason: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            reason: reason.into(),
+        }
+    }
+}
+
 impl Error {
     pub fn status_code(&self) -> StatusCode {
         match &self {
             Error::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Error::DbError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Error::NotFoundError => StatusCode::NOT_FOUND,
+            Error::UnauthorizedError(_) => StatusCode::UNAUTHORIZED,
+            Error::ForbiddenError(_) => StatusCode::FORBIDDEN,
+            Error::BadRequestError(_) => StatusCode::BAD_REQUEST,
+            Error::UnprocessableEntityError(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Error::ConflictError(_) => StatusCode::CONFLICT,
+            Error::TooManyRequestsError(_) => StatusCode::TOO_MANY_REQUESTS,
+            Error::ServiceUnavailableError(..) => StatusCode::SERVICE_UNAVAILABLE,
+            Error::RejectionError(status, _) => *status,
+            Error::ValidationError(_) => StatusCode::UNPROCESSABLE_ENTITY,
         }
     }
 
-    pub fn body(&self) -> Body {
+    /// Human readable explanation for the client. Internal errors stay vague
+    /// on purpose, the details only go to the logs.
+    pub fn detail(&self) -> String {
         match &self {
-            Error::UnexpectedError(_) => Body::empty(),
-            Error::DbError(_) => Body::empty(),
-            Error::NotFoundError => Body::empty(),
+            Error::UnexpectedError(_) => "an unexpected error occurred".to_string(),
+            Error::DbError(_) => "a database error occurred".to_string(),
+            Error::NotFoundError => "the requested resource does not exist".to_string(),
+            Error::UnauthorizedError(msg) => msg.clone(),
+            Error::ForbiddenError(msg) => msg.clone(),
+            Error::BadRequestError(msg) => msg.clone(),
+            Error::UnprocessableEntityError(msg) => msg.clone(),
+            Error::ConflictError(msg) => msg.clone(),
+            Error::TooManyRequestsError(retry_after) => {
+                format!("too many requests, try again in {retry_after}s")
+            }
+            Error::ServiceUnavailableError(retry_after, msg) => {
+                format!("{msg}, try again in {retry_after}s")
+            }
+            Error::RejectionError(_, msg) => msg.clone(),
+            Error::ValidationError(errors) => {
+                format!("{} field(s) failed validation", errors.len())
+            }
+        }
+    }
+
+    pub fn problem(&self) -> Problem {
+        let status = self.status_code();
+
+        // we don't publish problem type docs, so stick to the RFC default
+        Problem {
+            problem_type: "about:blank".to_string(),
+            title: status
+                .canonical_reason()
+                .unwrap_or("Unknown Error")
+                .to_string(),
+            status: status.as_u16(),
+            detail: self.detail(),
+            request_id: request_id::current(),
+            errors: match &self {
+                Error::ValidationError(errors) => errors.clone(),
+                _ => Vec::new(),
+            },
         }
     }
 }
 
 impl IntoResponse for Error {
     fn into_response(self) -> axum::response::Response {
-        let body = self.body();
         let status_code = self.status_code();
-        error!("Error: {status_code} : {body:?}");
-        Response::builder().status(status_code).body(body).unwrap()
+        let problem = self.problem();
+        error!("Error: {status_code} : {self:?}");
+
+        let mut response = (status_code, axum::Json(problem)).into_response();
+        response
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static(PROBLEM_CONTENT_TYPE));
+        // tells the client how to authenticate, required alongside a 401
+        if status_code == StatusCode::UNAUTHORIZED {
+            response
+                .headers_mut()
+                .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        }
+        if let Error::TooManyRequestsError(retry_after)
+        | Error::ServiceUnavailableError(retry_after, _) = self
+        {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(retry_after));
+        }
+        response
+    }
+}
+
+impl From<sqlx::Error> for Error {
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            // a CHECK constraint caught what validation let through (23514)
+            sqlx::Error::Database(db) if db.is_check_violation() => {
+                Error::UnprocessableEntityError(format!(
+                    "violates {}",
+                    db.constraint().unwrap_or("a check constraint")
+                ))
+            }
+            e => Error::DbError(e),
+        }
+    }
+}
+
+impl From<JsonRejection> for Error {
+    fn from(rejection: JsonRejection) -> Self {
+        Error::RejectionError(rejection.status(), rejection.body_text())
     }
 }
+
+impl From<PathRejection> for Error {
+    fn from(rejection: PathRejection) -> Self {
+        Error::RejectionError(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for Error {
+    fn from(rejection: QueryRejection) -> Self {
+        Error::RejectionError(rejection.status(), rejection.body_text())
+    }
+}
+
+/// Catch-all for unmatched routes so they get a problem body too
+pub async fn not_found() -> Response {
+    Error::NotFoundError.into_response()
+}
+
+/// Catch-all for routes that exist, but not with the method asked for
+pub async fn method_not_allowed() -> Response {
+    Error::RejectionError(
+        StatusCode::METHOD_NOT_ALLOWED,
+        "the method is not allowed for the requested resource".to_string(),
+    )
+    .into_response()
+}
@@ -0,0 +1,297 @@
+//! Validating JWTs from the company SSO, against the keys it publishes as a
+//! JWKS. The keys are cached, and refetched when they get old or a token shows
+//! up signed by a key we haven't seen, which is how rotation reaches us.
+
+use crate::auth::Scope;
+use crate::error::Error;
+use crate::settings::JwtSettings;
+use anyhow::{Context, anyhow};
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::jwk::{Jwk, PublicKeyUse};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation, decode, decode_header};
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// What a valid token told us about its bearer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenClaims {
+    pub subject: Option<String>,
+    pub scopes: Vec<Scope>,
+}
+
+#[derive(Debug)]
+pub struct JwtValidator {
+    source: JwksSource,
+    validation: Validation,
+    refresh_interval: Duration,
+    min_refresh_interval: Duration,
+    keys: RwLock<CachedKeys>,
+    /// held while fetching, so a burst of requests only fetches once
+    refreshing: tokio::sync::Mutex<()>,
+}
+
+#[derive(Debug)]
+enum JwksSource {
+    Url {
+        url: String,
+        client: reqwest::Client,
+    },
+    File(PathBuf),
+}
+
+#[derive(Debug, Default)]
+struct CachedKeys {
+    keys: Vec<Jwk>,
+    /// the last fetch that worked
+    fetched_at: Option<Instant>,
+    /// the last fetch started, whether or not it worked
+    attempted_at: Option<Instant>,
+}
+
+/// Only the keys are looked at, one at a time, so a key we can't use doesn't
+/// take the rest of the set down with it
+#[derive(Deserialize)]
+struct RawJwkSet {
+    keys: Vec<serde_json::Value>,
+}
+
+/// The claims we look at, anything else in the token is ignored
+#[derive(Deserialize)]
+struct Claims {
+    sub: Option<String>,
+    /// space separated, as in RFC 8693
+    scope: Option<String>,
+    /// some providers send a list under this name instead
+    scp: Option<ScpClaim>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ScpClaim {
+    List(Vec<String>),
+    Joined(String),
+}
+
+impl JwtValidator {
+    pub fn new(settings: &JwtSettings) -> anyhow::Result<Self> {
+        let source = match (&settings.jwks_url, &settings.jwks_path) {
+            (Some(url), None) => JwksSource::Url {
+                url: url.clone(),
+                client: reqwest::Client::builder()
+                    .timeout(Duration::from_millis(settings.jwks_timeout_ms))
+                    .build()
+                    .context("build jwks client")?,
+            },
+            (None, Some(path)) => JwksSource::File(path.clone()),
+            _ => return Err(anyhow!("exactly one of jwks_url and jwks_path must be set")),
+        };
+
+        // the algorithm is filled in per token, from its header
+        let mut validation = Validation::new(Algorithm::RS256);
+        validation.set_issuer(&[&settings.issuer]);
+        validation.set_audience(&[&settings.audience]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud"]);
+        validation.validate_nbf = true;
+        validation.leeway = settings.leeway_secs;
+
+        Ok(Self {
+            source,
+            validation,
+            refresh_interval: Duration::from_millis(settings.jwks_refresh_interval_ms),
+            min_refresh_interval: Duration::from_millis(settings.jwks_min_refresh_interval_ms),
+            keys: RwLock::default(),
+            refreshing: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    /// Fetches the keys now, replacing whatever was cached
+    pub async fn refresh(&self) -> anyhow::Result<()> {
+        let _refreshing = self.refreshing.lock().await;
+        self.keys.write().expect("jwks lock poisoned").attempted_at = Some(Instant::now());
+        self.fetch().await
+    }
+
+    /// Checks the signature, issuer, audience and expiry of `token`
+    pub async fn validate(self: &Arc<Self>, token: &str) -> Result<TokenClaims, Error> {
+        let header = decode_header(token)
+            .map_err(|_| Error::UnauthorizedError("the token is malformed".to_string()))?;
+
+        // shared secrets have no business in a published key set
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            return Err(Error::UnauthorizedError(format!(
+                "the token is signed with {:?}, which isn't accepted",
+                header.alg
+            )));
+        }
+
+        let jwk = self.key(header.kid.as_deref()).await?.ok_or_else(|| {
+            Error::UnauthorizedError("the token is signed by an unknown key".to_string())
+        })?;
+        // key algorithms include encryption ones, which no token is signed with
+        if let Some(alg) = jwk.common.key_algorithm
+            && Algorithm::from_str(&alg.to_string()).ok() != Some(header.alg)
+        {
+            return Err(Error::UnauthorizedError(format!(
+                "the token is signed with {:?}, but its key is for {alg}",
+                header.alg
+            )));
+        }
+        let key = DecodingKey::from_jwk(&jwk)
+            .map_err(|_| Error::UnauthorizedError("the token's key is unusable".to_string()))?;
+
+        let mut validation = self.validation.clone();
+        validation.algorithms = vec![header.alg];
+        let claims = decode::<Claims>(token, &key, &validation)
+            .map_err(|e| Error::UnauthorizedError(rejection(e.kind())))?
+            .claims;
+
+        let scopes = match (claims.scope, claims.scp) {
+            (Some(scope), _) | (None, Some(ScpClaim::Joined(scope))) => {
+                scope.split_whitespace().map(str::to_string).collect()
+            }
+            (None, Some(ScpClaim::List(scopes))) => scopes,
+            (None, None) => Vec::new(),
+        };
+
+        Ok(TokenClaims {
+            subject: claims.sub,
+            // scopes meant for other services grant nothing here
+            scopes: scopes.iter().filter_map(|s| s.parse().ok()).collect(),
+        })
+    }
+
+    /// The signing key with id `kid`, or the only key if the token names none
+    async fn key(self: &Arc<Self>, kid: Option<&str>) -> Result<Option<Jwk>, Error> {
+        let (found, fetched_at, attempted_at) = self.cached(kid);
+        // made up key ids and a provider that's down only get one fetch per
+        // `min_refresh_interval` between them
+        let may_fetch = attempted_at.is_none_or(|at| at.elapsed() >= self.min_refresh_interval);
+
+        if let (Some(jwk), Some(fetched_at)) = (&found, fetched_at) {
+            // old keys keep being served while they're refetched
+            if fetched_at.elapsed() >= self.refresh_interval && may_fetch {
+                let jwt = Arc::clone(self);
+                tokio::spawn(async move { jwt.refetch(attempted_at).await });
+            }
+            return Ok(Some(jwk.clone()));
+        }
+
+        // an unknown key may have just been rotated in, and with no keys at
+        // all there's nothing else to go on, so this request waits
+        if may_fetch {
+            self.refetch(attempted_at).await;
+        }
+
+        match self.cached(kid) {
+            (found, Some(_), _) => Ok(found),
+            // the provider is down rather than anything wrong with us, and
+            // won't be asked again until `min_refresh_interval` is up
+            (_, None, attempted_at) => {
+                let wait = attempted_at.map_or(Duration::ZERO, |at| {
+                    self.min_refresh_interval.saturating_sub(at.elapsed())
+                });
+                Err(Error::ServiceUnavailableError(
+                    wait.as_secs_f64().ceil().max(1.0) as u64,
+                    "the token's signing keys can't be fetched".to_string(),
+                ))
+            }
+        }
+    }
+
+    /// Fetches the keys unless someone else tried since `attempted_at`, in
+    /// which case their attempt is waited on instead
+    async fn refetch(&self, attempted_at: Option<Instant>) {
+        let _refreshing = self.refreshing.lock().await;
+        {
+            let mut cached = self.keys.write().expect("jwks lock poisoned");
+            if cached.attempted_at != attempted_at {
+                return;
+            }
+            cached.attempted_at = Some(Instant::now());
+        }
+
+        if let Err(e) = self.fetch().await {
+            warn!("refreshing jwks failed, keeping the old keys: {e:?}");
+        }
+    }
+
+    fn cached(&self, kid: Option<&str>) -> (Option<Jwk>, Option<Instant>, Option<Instant>) {
+        let cached = self.keys.read().expect("jwks lock poisoned");
+        let found = match kid {
+            Some(kid) => cached
+                .keys
+                .iter()
+                .find(|jwk| jwk.common.key_id.as_deref() == Some(kid)),
+            None if cached.keys.len() == 1 => cached.keys.first(),
+            None => None,
+        };
+
+        (found.cloned(), cached.fetched_at, cached.attempted_at)
+    }
+
+    /// Loads the keys from wherever they're published. Callers hold
+    /// `refreshing`.
+    async fn fetch(&self) -> anyhow::Result<()> {
+        let raw: RawJwkSet = match &self.source {
+            JwksSource::Url { url, client } => client
+                .get(url)
+                .send()
+                .await
+                .and_then(|resp| resp.error_for_status())
+                .with_context(|| format!("fetch jwks from {url}"))?
+                .json()
+                .await
+                .with_context(|| format!("parse jwks from {url}"))?,
+            JwksSource::File(path) => {
+                let json = tokio::fs::read(path)
+                    .await
+                    .with_context(|| format!("read jwks from {}", path.display()))?;
+                serde_json::from_slice(&json)
+                    .with_context(|| format!("parse jwks from {}", path.display()))?
+            }
+        };
+
+        let keys: Vec<Jwk> = raw
+            .keys
+            .into_iter()
+            .filter_map(|key| match serde_json::from_value::<Jwk>(key) {
+                Ok(jwk) => Some(jwk),
+                Err(e) => {
+                    debug!("skipping unusable jwk: {e}");
+                    None
+                }
+            })
+            .filter(|jwk| !matches!(jwk.common.public_key_use, Some(PublicKeyUse::Encryption)))
+            .collect();
+        if keys.is_empty() {
+            return Err(anyhow!("the jwks has no usable signing keys"));
+        }
+
+        info!("loaded {} signing key(s) from the jwks", keys.len());
+        let mut cached = self.keys.write().expect("jwks lock poisoned");
+        cached.keys = keys;
+        cached.fetched_at = Some(Instant::now());
+
+        Ok(())
+    }
+}
+
+/// Why a well formed token was turned away, for the client
+fn rejection(kind: &ErrorKind) -> String {
+    match kind {
+        ErrorKind::ExpiredSignature => "the token has expired".to_string(),
+        ErrorKind::ImmatureSignature => "the token isn't valid yet".to_string(),
+        ErrorKind::InvalidIssuer => "the token is from an untrusted issuer".to_string(),
+        ErrorKind::InvalidAudience => "the token is meant for another audience".to_string(),
+        ErrorKind::MissingRequiredClaim(claim) => format!("the token has no {claim} claim"),
+        ErrorKind::InvalidSignature => "the token's signature doesn't match".to_string(),
+        _ => "the token is invalid".to_string(),
+    }
+}
@@ -3,15 +3,30 @@
 // @deinum - testing release-plz version bump
 // testing automatic version detection
 pub(crate) mod app;
+pub mod cli;
+pub(crate) mod db;
 pub(crate) mod error;
+pub(crate) mod extract;
+pub(crate) mod jwt;
+pub(crate) mod metrics;
+pub(crate) mod migrate;
+pub(crate) mod rate_limit;
+pub(crate) mod request_id;
 pub(crate) mod routes;
 pub(crate) mod run;
-pub(crate) mod telemetry;
+pub(crate) mod server;
+pub(crate) mod shutdown;
+pub(crate) mod tls;
 
 // main entrypoint to lib
+pub use error::RunError;
 pub use run::run;
 
 // tests need access to these
+pub mod api_keys;
+pub mod auth;
 pub mod settings;
+pub mod telemetry;
 pub mod types;
 pub use app::App;
+pub use shutdown::Shutdown;
@@ -1,4 +1,19 @@
+use gha_demo::cli::Cli;
+use std::process::ExitCode;
+
 #[tokio::main]
-pub async fn main() {
-    gha_demo::run().await;
+pub async fn main() -> ExitCode {
+    let cli = match Cli::try_parse_from(std::env::args_os()) {
+        Ok(cli) => cli,
+        Err(e) => {
+            // prints usage, or help / version which aren't failures
+            let _ = e.print();
+            return ExitCode::from(e.exit_code() as u8);
+        }
+    };
+
+    match gha_demo::run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => e.exit_code(),
+    }
 }
@@ -0,0 +1,162 @@
+use crate::db::Db;
+use crate::error::Result;
+use anyhow::Context;
+use axum::extract::{MatchedPath, Request, State};
+use axum::http::{HeaderValue, StatusCode, header::CONTENT_TYPE};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use std::time::Instant;
+
+// label for requests that didn't match any route, so scanners hitting random
+// paths can't blow up our label cardinality
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// Request rate, errors and duration per route, plus the state of the db pool.
+/// Each app gets its own registry rather than using the global default so
+/// several can live in one process, like in the tests.
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    errors: IntCounterVec,
+    duration: HistogramVec,
+    pool_connections: IntGaugeVec,
+    db: Db,
+}
+
+impl Metrics {
+    pub fn new(db: Db) -> Result<Self> {
+        let registry = Registry::new();
+        let labels = &["method", "route", "status"];
+
+        let requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "Number of HTTP requests handled"),
+            labels,
+        )
+        .context("create request counter")?;
+
+        let errors = IntCounterVec::new(
+            Opts::new(
+                "http_request_errors_total",
+                "Number of HTTP requests that ended in a server error (5xx)",
+            ),
+            labels,
+        )
+        .context("create error counter")?;
+
+        let duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time taken to handle HTTP requests",
+            ),
+            labels,
+        )
+        .context("create duration histogram")?;
+
+        // waiting above zero means requests are queueing for a connection,
+        // which is the saturation signal to alert on
+        let pool_connections = IntGaugeVec::new(
+            Opts::new(
+                "db_pool_connections",
+                "Connections in the database pool by state (size, idle, in_use, waiting, max)",
+            ),
+            &["state"],
+        )
+        .context("create pool gauge")?;
+
+        registry
+            .register(Box::new(requests.clone()))
+            .context("register request counter")?;
+        registry
+            .register(Box::new(errors.clone()))
+            .context("register error counter")?;
+        registry
+            .register(Box::new(duration.clone()))
+            .context("register duration histogram")?;
+        registry
+            .register(Box::new(pool_connections.clone()))
+            .context("register pool gauge")?;
+
+        Ok(Self {
+            registry,
+            requests,
+            errors,
+            duration,
+            pool_connections,
+            db,
+        })
+    }
+
+    fn observe(&self, method: &str, route: &str, status: StatusCode, elapsed: f64) {
+        let labels = [method, route, status.as_str()];
+
+        self.requests.with_label_values(&labels).inc();
+        self.duration.with_label_values(&labels).observe(elapsed);
+        if status.is_server_error() {
+            self.errors.with_label_values(&labels).inc();
+        }
+    }
+
+    /// Renders everything in the prometheus text format, sampling the pool
+    /// gauges first since they're point in time values
+    pub fn render(&self) -> Result<String> {
+        let pool = self.db.pool();
+        let size = i64::from(pool.size());
+        let idle = pool.num_idle() as i64;
+        let max = i64::from(pool.options().get_max_connections());
+
+        self.pool_connections.with_label_values(&["size"]).set(size);
+        self.pool_connections.with_label_values(&["idle"]).set(idle);
+        self.pool_connections
+            .with_label_values(&["in_use"])
+            .set(size - idle);
+        self.pool_connections
+            .with_label_values(&["waiting"])
+            .set(self.db.waiting());
+        self.pool_connections.with_label_values(&["max"]).set(max);
+
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .context("encode metrics")?;
+
+        Ok(String::from_utf8(buffer).context("metrics are not utf8")?)
+    }
+}
+
+/// Records every request passing through it against [`Metrics`]
+pub async fn track_metrics(State(metrics): State<Metrics>, req: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let method = req.method().to_string();
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| UNMATCHED_ROUTE.to_string());
+
+    let response = next.run(req).await;
+
+    metrics.observe(
+        &method,
+        &route,
+        response.status(),
+        start.elapsed().as_secs_f64(),
+    );
+
+    response
+}
+
+pub async fn metrics_handler(State(metrics): State<Metrics>) -> Result<Response> {
+    let body = metrics.render()?;
+
+    let mut response = body.into_response();
+    response.headers_mut().insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("text/plain; version=0.0.4"),
+    );
+
+    Ok(response)
+}
@@ -0,0 +1,94 @@
+use crate::cli::MigrateCommand;
+use crate::db::MIGRATOR;
+use anyhow::{Context, bail};
+use sqlx::PgPool;
+use sqlx::migrate::Migrate;
+use tracing::info;
+
+/// Runs a `gha_demo migrate` subcommand against `db`
+pub async fn migrate(command: MigrateCommand, db: &PgPool) -> anyhow::Result<()> {
+    match command {
+        MigrateCommand::Status => status(db).await,
+        MigrateCommand::Run => {
+            let pending = pending(db).await?;
+            MIGRATOR.run(db).await.context("apply migrations")?;
+            info!("applied {pending} migration(s)");
+            status(db).await
+        }
+        MigrateCommand::Revert { target } => {
+            if target != 0 && !MIGRATOR.iter().any(|m| m.version == target) {
+                bail!("unknown migration version {target}");
+            }
+            MIGRATOR
+                .undo(db, target)
+                .await
+                .with_context(|| format!("revert migrations to version {target}"))?;
+            info!("reverted migrations to version {target}");
+            status(db).await
+        }
+    }
+}
+
+async fn pending(db: &PgPool) -> anyhow::Result<usize> {
+    let mut conn = db.acquire().await.context("acquire connection")?;
+    conn.ensure_migrations_table()
+        .await
+        .context("create migrations table")?;
+    let applied = conn
+        .list_applied_migrations()
+        .await
+        .context("list applied migrations")?;
+
+    Ok(MIGRATOR
+        .iter()
+        .filter(|m| m.migration_type.is_up_migration())
+        .filter(|m| !applied.iter().any(|a| a.version == m.version))
+        .count())
+}
+
+/// Prints one line per migration, the ones this build knows about and any
+/// the database has that it doesn't. Only reads, so it's safe to point at a
+/// database we don't mean to migrate.
+async fn status(db: &PgPool) -> anyhow::Result<()> {
+    let mut conn = db.acquire().await.context("acquire connection")?;
+    let migrated: bool = sqlx::query_scalar("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+        .fetch_one(&mut *conn)
+        .await
+        .context("look for migrations table")?;
+    let (dirty, applied) = if migrated {
+        let dirty = conn.dirty_version().await.context("read dirty version")?;
+        let applied = conn
+            .list_applied_migrations()
+            .await
+            .context("list applied migrations")?;
+        (dirty, applied)
+    } else {
+        println!("no migrations applied");
+        (None, Vec::new())
+    };
+
+    for migration in MIGRATOR
+        .iter()
+        .filter(|m| m.migration_type.is_up_migration())
+    {
+        let state = match applied.iter().find(|a| a.version == migration.version) {
+            _ if dirty == Some(migration.version) => "dirty",
+            Some(a) if a.checksum != migration.checksum => "modified",
+            Some(_) => "applied",
+            None => "pending",
+        };
+        println!(
+            "{:<16}{:<10}{}",
+            migration.version, state, migration.description
+        );
+    }
+
+    for unknown in applied
+        .iter()
+        .filter(|a| !MIGRATOR.iter().any(|m| m.version == a.version))
+    {
+        println!("{:<16}{:<10}", unknown.version, "unknown");
+    }
+
+    Ok(())
+}
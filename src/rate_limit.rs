@@ -0,0 +1,376 @@
+//! Token bucket rate limiting, per client and route group. A client is the api
+//! key it sent if that's valid, its ip otherwise. Buckets live in memory, or in
+//! the db so every replica draws from the same ones.
+
+use crate::api_keys::{ApiKey, hash, is_api_key};
+use crate::app::AppState;
+use crate::auth::bearer_token;
+use crate::db::Db;
+use crate::error::Error;
+use crate::settings::{RateLimitBackend, RateLimitSettings, RouteLimit};
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use ipnet::IpNet;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+const RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+const RATELIMIT_POLICY: HeaderName = HeaderName::from_static("ratelimit-policy");
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+
+/// How many takes between sweeps of the buckets that have filled back up
+const SWEEP_EVERY: u64 = 1000;
+
+/// How long a key found valid skips the ip's bucket on its way to its own
+const KNOWN_KEY_TTL: Duration = Duration::from_secs(60);
+
+/// The routes that share a limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteGroup {
+    Cats,
+    Latency,
+}
+
+impl RouteGroup {
+    fn as_str(&self) -> &'static str {
+        match self {
+            RouteGroup::Cats => "cats",
+            RouteGroup::Latency => "latency",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    backend: Backend,
+    cats: RouteLimit,
+    latency: RouteLimit,
+    trusted_proxies: Vec<IpNet>,
+    takes: AtomicU64,
+    /// hashes of api keys recently found valid, and whose they are. Only
+    /// picks the bucket, auth still checks the key every time.
+    known_keys: Mutex<HashMap<Vec<u8>, (Uuid, Instant)>>,
+}
+
+#[derive(Debug)]
+enum Backend {
+    Memory(Mutex<HashMap<String, Bucket>>),
+    Postgres(Db),
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+    /// once past this the bucket is as good as new, and can be dropped
+    full_at: Instant,
+}
+
+/// How a request fared against its bucket
+#[derive(Debug)]
+struct Decision {
+    taken: bool,
+    remaining: f64,
+    limit: RouteLimit,
+}
+
+impl RateLimiter {
+    /// `settings` are expected to have been validated
+    pub fn new(settings: &RateLimitSettings, db: &Db) -> Self {
+        let backend = match settings.backend {
+            RateLimitBackend::Memory => Backend::Memory(Mutex::default()),
+            RateLimitBackend::Postgres => Backend::Postgres(db.clone()),
+        };
+
+        Self {
+            backend,
+            cats: settings.cats,
+            latency: settings.latency,
+            trusted_proxies: settings
+                .trusted_proxies
+                .iter()
+                .filter_map(|proxy| {
+                    proxy
+                        .parse()
+                        .or_else(|_| proxy.parse::<IpAddr>().map(IpNet::from))
+                        .ok()
+                })
+                .collect(),
+            takes: AtomicU64::new(0),
+            known_keys: Mutex::default(),
+        }
+    }
+
+    fn limit(&self, group: RouteGroup) -> RouteLimit {
+        match group {
+            RouteGroup::Cats => self.cats,
+            RouteGroup::Latency => self.latency,
+        }
+    }
+
+    /// Takes a token from the bucket for `key`. A db that's down lets
+    /// everyone through rather than no one, which is `None`.
+    async fn take(&self, key: &str, limit: RouteLimit) -> Option<Decision> {
+        let sweep = self.takes.fetch_add(1, Ordering::Relaxed) % SWEEP_EVERY == SWEEP_EVERY - 1;
+        let burst = f64::from(limit.burst);
+
+        match &self.backend {
+            Backend::Memory(buckets) => {
+                let now = Instant::now();
+                let mut buckets = buckets.lock().expect("rate limit lock poisoned");
+                if sweep {
+                    buckets.retain(|_, bucket| bucket.full_at > now);
+                }
+
+                let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+                    tokens: burst,
+                    updated_at: now,
+                    full_at: now,
+                });
+                let refilled = bucket.tokens
+                    + now.duration_since(bucket.updated_at).as_secs_f64() * limit.per_second;
+                let mut remaining = refilled.min(burst);
+                let taken = remaining >= 1.0;
+                if taken {
+                    remaining -= 1.0;
+                }
+
+                bucket.tokens = remaining;
+                bucket.updated_at = now;
+                bucket.full_at = now + seconds(burst - remaining, limit.per_second);
+
+                Some(Decision {
+                    taken,
+                    remaining,
+                    limit,
+                })
+            }
+            Backend::Postgres(db) => {
+                if sweep {
+                    let db = db.clone();
+                    tokio::spawn(async move {
+                        let swept = async {
+                            let mut conn = db.acquire().await?;
+                            sqlx::query("DELETE FROM rate_limit_buckets WHERE full_at < now()")
+                                .execute(&mut *conn)
+                                .await
+                        };
+                        if let Err(e) = swept.await {
+                            warn!("sweeping rate limit buckets failed: {e:?}");
+                        }
+                    });
+                }
+
+                let taken = async {
+                    let mut conn = db.acquire().await?;
+                    sqlx::query_as::<_, (bool, f64)>(
+                        "SELECT taken, remaining FROM rate_limit_take($1, $2, $3)",
+                    )
+                    .bind(key)
+                    .bind(burst)
+                    .bind(limit.per_second)
+                    .fetch_one(&mut *conn)
+                    .await
+                };
+                match taken.await {
+                    Ok((taken, remaining)) => Some(Decision {
+                        taken,
+                        remaining,
+                        limit,
+                    }),
+                    Err(e) => {
+                        warn!("rate limiting failed, letting the request through: {e:?}");
+                        None
+                    }
+                }
+            }
+        }
+    }
+
+    /// The api key `token` turned out to be not long ago, if it did
+    fn known_key(&self, token: &str) -> Option<Uuid> {
+        let known = self.known_keys.lock().expect("known keys lock poisoned");
+        known
+            .get(&hash(token))
+            .filter(|(_, at)| at.elapsed() < KNOWN_KEY_TTL)
+            .map(|(id, _)| *id)
+    }
+
+    fn remember_key(&self, token: &str, id: Uuid) {
+        let mut known = self.known_keys.lock().expect("known keys lock poisoned");
+        known.retain(|_, (_, at)| at.elapsed() < KNOWN_KEY_TTL);
+        known.insert(hash(token), (id, Instant::now()));
+    }
+
+    /// The ip's bucket, for requests without a valid api key
+    fn ip_bucket(&self, group: RouteGroup, request: &Request) -> String {
+        let peer = request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        match peer {
+            Some(peer) => format!(
+                "{}:ip:{}",
+                group.as_str(),
+                self.client_ip(peer, request.headers())
+            ),
+            None => format!("{}:ip:unknown", group.as_str()),
+        }
+    }
+
+    /// The address the request came from, looking past our own proxies
+    fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let trusted = |ip: &IpAddr| self.trusted_proxies.iter().any(|net| net.contains(ip));
+        if !trusted(&peer) {
+            return peer;
+        }
+
+        // each proxy appends whoever it heard from, so walking back from the
+        // end the first hop we don't run is the client. Anything before that
+        // could have been made up by the client.
+        let hops: Vec<&str> = headers
+            .get_all(X_FORWARDED_FOR)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .collect();
+        let mut client = peer;
+        for hop in hops.into_iter().rev() {
+            let Some(ip) = parse_hop(hop.trim()) else {
+                debug!("x-forwarded-for hop {hop:?} isn't an ip, stopping there");
+                break;
+            };
+            client = ip;
+            if !trusted(&ip) {
+                break;
+            }
+        }
+
+        client
+    }
+}
+
+/// A hop as proxies write them, with or without a port
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    hop.parse()
+        .ok()
+        .or_else(|| hop.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+}
+
+fn seconds(tokens: f64, per_second: f64) -> Duration {
+    Duration::from_secs_f64((tokens / per_second).max(0.0))
+}
+
+impl Decision {
+    /// Seconds until there's a token to take again
+    fn retry_after(&self) -> u64 {
+        (seconds(1.0 - self.remaining, self.limit.per_second)
+            .as_secs_f64()
+            .ceil() as u64)
+            .max(1)
+    }
+
+    /// The `RateLimit-*` headers from draft-ietf-httpapi-ratelimit-headers
+    fn write_headers(&self, headers: &mut HeaderMap) {
+        let burst = f64::from(self.limit.burst);
+        let reset = seconds(burst - self.remaining, self.limit.per_second)
+            .as_secs_f64()
+            .ceil() as u64;
+        let window = seconds(burst, self.limit.per_second).as_secs_f64().ceil() as u64;
+
+        headers.insert(RATELIMIT_LIMIT, HeaderValue::from(self.limit.burst));
+        headers.insert(
+            RATELIMIT_REMAINING,
+            HeaderValue::from(self.remaining.floor() as u64),
+        );
+        headers.insert(RATELIMIT_RESET, HeaderValue::from(reset));
+        if let Ok(policy) = HeaderValue::try_from(format!("{};w={window}", self.limit.burst)) {
+            headers.insert(RATELIMIT_POLICY, policy);
+        }
+    }
+}
+
+/// Turns away clients that have emptied their bucket for `group` with a 429
+pub async fn rate_limit(
+    State((state, group)): State<(AppState, RouteGroup)>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let Some(limiter) = state.rate_limiter.clone() else {
+        return next.run(request).await;
+    };
+    let limit = limiter.limit(group);
+    let ip_bucket = limiter.ip_bucket(group, &request);
+
+    let token = bearer_token(request.headers())
+        .ok()
+        .flatten()
+        .filter(|token| is_api_key(token))
+        .map(str::to_string);
+    let bucket = match token {
+        None => ip_bucket,
+        Some(token) => match limiter.known_key(&token) {
+            Some(id) => format!("{}:key:{id}", group.as_str()),
+            None => {
+                // keys we haven't seen pay from the ip's bucket before they
+                // cost a lookup, so made up ones can't flood the db
+                let decision = limiter.take(&ip_bucket, limit).await;
+                if decision.as_ref().is_some_and(|decision| !decision.taken) {
+                    return respond(decision, &ip_bucket, request, next).await;
+                }
+
+                let found = async {
+                    let mut conn = state.db.acquire().await?;
+                    ApiKey::find_active(&mut *conn, &token).await
+                };
+                match found.await {
+                    Ok(Some(api_key)) => {
+                        let id = api_key.id;
+                        limiter.remember_key(&token, id);
+                        request.extensions_mut().insert(api_key);
+                        format!("{}:key:{id}", group.as_str())
+                    }
+                    // auth turns it away, the ip has paid for it already
+                    Ok(None) => return respond(decision, &ip_bucket, request, next).await,
+                    Err(e) => {
+                        warn!("looking up api key for rate limiting failed: {e:?}");
+                        return respond(decision, &ip_bucket, request, next).await;
+                    }
+                }
+            }
+        },
+    };
+
+    let decision = limiter.take(&bucket, limit).await;
+    respond(decision, &bucket, request, next).await
+}
+
+/// Runs the request if `decision` let it through, with the `RateLimit-*`
+/// headers for `bucket` either way
+async fn respond(
+    decision: Option<Decision>,
+    bucket: &str,
+    request: Request,
+    next: Next,
+) -> Response {
+    let mut response = match &decision {
+        Some(decision) if !decision.taken => {
+            debug!("rate limited {bucket}");
+            Error::TooManyRequestsError(decision.retry_after()).into_response()
+        }
+        _ => next.run(request).await,
+    };
+    if let Some(decision) = decision {
+        decision.write_headers(response.headers_mut());
+    }
+    response
+}
@@ -0,0 +1,43 @@
+use axum::extract::Request;
+use axum::http::{HeaderName, HeaderValue};
+use axum::middleware::Next;
+use axum::response::Response;
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+// anything longer than this from a client is more likely abuse than an id
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// The id of the request currently being handled, if called from within one
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Uses the client's `X-Request-Id` if it looks sane, otherwise makes one up,
+/// and makes it available through [`current`] for the rest of the request.
+/// The id is echoed back on the response either way.
+pub async fn request_id(req: Request, next: Next) -> Response {
+    // v7 so generated ids sort by time
+    let id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty() && v.len() <= MAX_REQUEST_ID_LEN)
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::now_v7().to_string());
+
+    // only ever visible ascii at this point, so this can't fail
+    let header = HeaderValue::from_str(&id).ok();
+
+    let mut response = REQUEST_ID.scope(id, next.run(req)).await;
+    if let Some(header) = header {
+        response.headers_mut().insert(REQUEST_ID_HEADER, header);
+    }
+
+    response
+}
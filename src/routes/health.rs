@@ -1,5 +1,171 @@
-use axum::http::StatusCode;
+use crate::app::AppState;
+use crate::db::{Db, MIGRATOR};
+use crate::shutdown::Shutdown;
+use axum::{Json, extract::State, http::StatusCode};
+use serde::{Deserialize, Serialize};
+use sqlx::migrate::Migrate;
+use std::time::Duration;
+use tracing::warn;
+use utoipa::ToSchema;
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    Fail,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct Check {
+    pub name: String,
+    pub status: CheckStatus,
+    /// why the check failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct ReadinessReport {
+    /// `pass` only if every check passed
+    pub status: CheckStatus,
+    pub checks: Vec<Check>,
+}
+
+/// Kept around for probes that predate `/health/live`
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "health",
+    responses((status = OK, description = "The service is up"))
+)]
 pub async fn health() -> StatusCode {
     StatusCode::OK
 }
+
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    tag = "health",
+    responses((status = OK, description = "The process is up"))
+)]
+pub async fn live() -> StatusCode {
+    StatusCode::OK
+}
+
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    tag = "health",
+    responses(
+        (status = OK, description = "Ready to take traffic", body = ReadinessReport),
+        (status = SERVICE_UNAVAILABLE, description = "At least one check failed", body = ReadinessReport),
+    )
+)]
+pub async fn ready(State(app_state): State<AppState>) -> (StatusCode, Json<ReadinessReport>) {
+    let timeout = Duration::from_millis(app_state.application.readiness_timeout_ms);
+
+    let checks = vec![
+        run_check("shutdown", timeout, check_shutdown(&app_state.shutdown)).await,
+        run_check("database", timeout, check_database(&app_state.db)).await,
+        run_check("migrations", timeout, check_migrations(&app_state.db)).await,
+    ];
+
+    let (status_code, status) = if checks.iter().all(|c| c.status == CheckStatus::Pass) {
+        (StatusCode::OK, CheckStatus::Pass)
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, CheckStatus::Fail)
+    };
+
+    (status_code, Json(ReadinessReport { status, checks }))
+}
+
+async fn run_check(
+    name: &str,
+    timeout: Duration,
+    check: impl Future<Output = Result<(), String>>,
+) -> Check {
+    let result = match tokio::time::timeout(timeout, check).await {
+        Ok(result) => result,
+        Err(_) => Err(format!("timed out after {}ms", timeout.as_millis())),
+    };
+
+    if let Err(detail) = &result {
+        warn!("readiness check {name} failed: {detail}");
+    }
+
+    Check {
+        name: name.to_string(),
+        status: if result.is_ok() {
+            CheckStatus::Pass
+        } else {
+            CheckStatus::Fail
+        },
+        detail: result.err(),
+    }
+}
+
+/// We're on our way out, so stop sending us traffic
+async fn check_shutdown(shutdown: &Shutdown) -> Result<(), String> {
+    if shutdown.is_triggered() {
+        return Err("shutting down".to_string());
+    }
+
+    Ok(())
+}
+
+async fn check_database(db: &Db) -> Result<(), String> {
+    let unreachable = |e: sqlx::Error| {
+        // the details are for us, not whoever is probing
+        warn!("database ping failed: {e:?}");
+        "database unreachable".to_string()
+    };
+
+    let mut conn = db.acquire().await.map_err(unreachable)?;
+    sqlx::query("SELECT 1")
+        .execute(&mut *conn)
+        .await
+        .map_err(unreachable)?;
+
+    Ok(())
+}
+
+/// The database should have every migration this build embeds, none of them
+/// edited after the fact. Ones it doesn't know about are fine, a newer release
+/// applies its migrations before the old replicas are gone.
+async fn check_migrations(db: &Db) -> Result<(), String> {
+    let unreadable = |e| {
+        warn!("reading applied migrations failed: {e:?}");
+        "could not read applied migrations".to_string()
+    };
+
+    let mut conn = db.acquire().await.map_err(|e| unreadable(e.into()))?;
+
+    if let Some(version) = conn.dirty_version().await.map_err(unreadable)? {
+        return Err(format!("migration {version} is partially applied"));
+    }
+
+    let applied = conn.list_applied_migrations().await.map_err(unreadable)?;
+
+    for migration in MIGRATOR
+        .iter()
+        .filter(|m| m.migration_type.is_up_migration())
+    {
+        match applied.iter().find(|a| a.version == migration.version) {
+            None => {
+                return Err(format!(
+                    "migration {} has not been applied",
+                    migration.version
+                ));
+            }
+            Some(a) if a.checksum != migration.checksum => {
+                return Err(format!(
+                    "migration {} was modified after being applied",
+                    migration.version
+                ));
+            }
+            Some(_) => {}
+        }
+    }
+
+    Ok(())
+}
@@ -1,10 +1,23 @@
-use crate::{app::AppState, error::Result};
+use crate::{
+    app::AppState,
+    error::{PROBLEM_CONTENT_TYPE, Problem, Result},
+};
 use axum::{extract::State, http::StatusCode};
 use rand::Rng;
 use std::time::Duration;
 
 const UPPER_RANGE: u16 = 1000;
 
+/// Sleeps for a random amount of time under a second, handy for load testing
+#[utoipa::path(
+    get,
+    path = "/latency",
+    tag = "latency",
+    responses(
+        (status = OK, description = "Finished the simulated work"),
+        (status = TOO_MANY_REQUESTS, description = "Over the rate limit, retry after `Retry-After` seconds", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+    )
+)]
 pub async fn latency(State(mut app_state): State<AppState>) -> Result<StatusCode> {
     // (simulate work)
     let millis_to_work = app_state.rng.random_range(0..UPPER_RANGE);
@@ -1,3 +1,5 @@
 pub mod health;
 pub mod latency;
+pub mod openapi;
 pub mod v1;
+pub mod v2;
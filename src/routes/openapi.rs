@@ -0,0 +1,75 @@
+use crate::error::{FieldError, Problem};
+use crate::routes::health::ReadinessReport;
+use crate::routes::{health, latency, v1::cats as v1_cats, v2::cats as v2_cats};
+use crate::types::v1::types::{Cat, EyeColor};
+use crate::types::v2::types::CatPage;
+use axum::Json;
+use axum::response::Html;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+/// Generated from the handlers and types, served at `/openapi.json`. The
+/// committed `openapi.json` at the repo root is checked against this in the
+/// tests, run them with `UPDATE_OPENAPI=1` to regenerate it.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Cool Cat Club API", version = "1"),
+    paths(
+        health::health,
+        health::live,
+        health::ready,
+        latency::latency,
+        v1_cats::get::get_all_cats,
+        v1_cats::get::get_cat,
+        v1_cats::post::create_cat,
+        v1_cats::put::replace_cat,
+        v1_cats::patch::update_cat,
+        v1_cats::delete::delete_cat,
+        v2_cats::get::get_cat_page,
+    ),
+    components(schemas(Cat, EyeColor, CatPage, Problem, FieldError, ReadinessReport)),
+    modifiers(&BearerAuth)
+)]
+pub struct ApiDoc;
+
+/// Declares the `bearer` scheme the cat routes reference
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some(
+                        "An api key minted with `gha_demo keys mint`, or a JWT from the SSO when enabled",
+                    ))
+                    .build(),
+            ),
+        );
+    }
+}
+
+pub async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+// redoc pulls itself from a CDN, so we don't have to vendor any assets
+const DOCS_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+  <head>
+    <title>Cool Cat Club API</title>
+    <meta charset="utf-8" />
+  </head>
+  <body>
+    <redoc spec-url="/openapi.json"></redoc>
+    <script src="https://cdn.redocly.com/redoc/latest/bundles/redoc.standalone.js"></script>
+  </body>
+</html>
+"#;
+
+pub async fn docs() -> Html<&'static str> {
+    Html(DOCS_PAGE)
+}
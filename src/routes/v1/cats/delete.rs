@@ -0,0 +1,32 @@
+use crate::app::AppState;
+use crate::auth::{Authorized, CatsWrite};
+use crate::error::{PROBLEM_CONTENT_TYPE, Problem, Result};
+use crate::extract::Path;
+use crate::types::v1::types::Cat;
+use axum::{extract::State, http::StatusCode};
+use uuid::Uuid;
+
+#[utoipa::path(
+    delete,
+    path = "/v1/cats/{cool_cat_club_id}",
+    tag = "cats",
+    security(("bearer" = ["cats:write"])),
+    params(("cool_cat_club_id" = Uuid, Path)),
+    responses(
+        (status = NO_CONTENT, description = "The cat was removed"),
+        (status = NOT_FOUND, description = "No cat with this id", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = UNAUTHORIZED, description = "Missing or invalid api key or token", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = SERVICE_UNAVAILABLE, description = "The token's signing keys can't be fetched, retry after `Retry-After` seconds", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = FORBIDDEN, description = "The api key or token lacks the cats:write scope", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = TOO_MANY_REQUESTS, description = "Over the rate limit, retry after `Retry-After` seconds", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+    )
+)]
+pub async fn delete_cat(
+    _: Authorized<CatsWrite>,
+    State(app_state): State<AppState>,
+    Path(cool_cat_club_id): Path<Uuid>,
+) -> Result<StatusCode> {
+    Cat::delete_from_db(&mut *app_state.db.acquire().await?, cool_cat_club_id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
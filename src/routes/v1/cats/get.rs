@@ -1,34 +1,64 @@
 use crate::{
     app::AppState,
-    error::{Error, Result},
-    types::v1::types::Cat,
-};
-use axum::{
-    Json,
-    extract::{Path, State},
-    http::StatusCode,
+    auth::{Authorized, CatsRead},
+    error::{Error, PROBLEM_CONTENT_TYPE, Problem, Result},
+    extract::{Json, Path, Query},
+    types::v1::types::{Cat, CatFilter},
 };
+use axum::{extract::State, http::StatusCode};
 use uuid::Uuid;
 
+#[utoipa::path(
+    get,
+    path = "/v1/cats",
+    tag = "cats",
+    security((), ("bearer" = ["cats:read"])),
+    params(CatFilter),
+    responses(
+        (status = OK, description = "Every matching cat", body = Vec<Cat>),
+        (status = BAD_REQUEST, description = "Unknown or malformed query parameter", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = UNAUTHORIZED, description = "Missing or invalid api key or token", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = SERVICE_UNAVAILABLE, description = "The token's signing keys can't be fetched, retry after `Retry-After` seconds", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = FORBIDDEN, description = "The api key or token lacks the cats:read scope", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = TOO_MANY_REQUESTS, description = "Over the rate limit, retry after `Retry-After` seconds", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+    )
+)]
 pub async fn get_all_cats(
+    _: Authorized<CatsRead>,
     State(app_state): State<AppState>,
+    Query(filter): Query<CatFilter>,
 ) -> Result<(StatusCode, Json<Vec<Cat>>)> {
-    // fetch all cats from the database
-    let cats = sqlx::query_as::<_, Cat>("SELECT * FROM cats")
-        .fetch_all(&app_state.db)
+    // fetch all matching cats from the database
+    let cats = filter
+        .fetch_from_db(&mut *app_state.db.acquire().await?)
         .await?;
 
     Ok((StatusCode::OK, Json(cats)))
 }
 
+#[utoipa::path(
+    get,
+    path = "/v1/cats/{cool_cat_club_id}",
+    tag = "cats",
+    security((), ("bearer" = ["cats:read"])),
+    params(("cool_cat_club_id" = Uuid, Path)),
+    responses(
+        (status = OK, description = "The cat", body = Cat),
+        (status = BAD_REQUEST, description = "Malformed id", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = NOT_FOUND, description = "No cat with this id", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = UNAUTHORIZED, description = "Missing or invalid api key or token", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = SERVICE_UNAVAILABLE, description = "The token's signing keys can't be fetched, retry after `Retry-After` seconds", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = FORBIDDEN, description = "The api key or token lacks the cats:read scope", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = TOO_MANY_REQUESTS, description = "Over the rate limit, retry after `Retry-After` seconds", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+    )
+)]
 pub async fn get_cat(
+    _: Authorized<CatsRead>,
     State(app_state): State<AppState>,
     Path(cool_cat_club_id): Path<Uuid>,
 ) -> Result<(StatusCode, Json<Cat>)> {
-    // fetch all cats from the database
-    let cat = sqlx::query_as::<_, Cat>("SELECT * FROM cats WHERE cool_cat_club_id = $1")
-        .bind(cool_cat_club_id)
-        .fetch_optional(&app_state.db)
+    // fetch the cat from the database
+    let cat = Cat::find_in_db(&mut *app_state.db.acquire().await?, cool_cat_club_id)
         .await?
         .ok_or(Error::NotFoundError)?;
 
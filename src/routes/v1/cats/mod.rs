@@ -1,3 +1,6 @@
+pub(crate) mod delete;
 pub(crate) mod get;
+pub(crate) mod patch;
 pub(crate) mod post;
+pub(crate) mod put;
 pub mod types;
@@ -0,0 +1,87 @@
+use crate::app::AppState;
+use crate::auth::{Authorized, CatsWrite};
+use crate::error::{Error, PROBLEM_CONTENT_TYPE, Problem, Result};
+use crate::extract::{Json, Path};
+use crate::types::v1::types::Cat;
+use anyhow::Context;
+use axum::{extract::State, http::StatusCode};
+use serde_json::Value;
+use sqlx::Acquire;
+use uuid::Uuid;
+
+/// Partially updates a cat using JSON Merge Patch (RFC 7396) semantics
+#[utoipa::path(
+    patch,
+    path = "/v1/cats/{cool_cat_club_id}",
+    tag = "cats",
+    security(("bearer" = ["cats:write"])),
+    params(("cool_cat_club_id" = Uuid, Path)),
+    request_body(content = Object, content_type = "application/merge-patch+json"),
+    responses(
+        (status = OK, description = "The updated cat", body = Cat),
+        (status = BAD_REQUEST, description = "The patch tries to change the id", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = NOT_FOUND, description = "No cat with this id", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = UNPROCESSABLE_ENTITY, description = "The patched cat failed validation", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = UNAUTHORIZED, description = "Missing or invalid api key or token", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = SERVICE_UNAVAILABLE, description = "The token's signing keys can't be fetched, retry after `Retry-After` seconds", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = FORBIDDEN, description = "The api key or token lacks the cats:write scope", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = TOO_MANY_REQUESTS, description = "Over the rate limit, retry after `Retry-After` seconds", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+    )
+)]
+pub async fn update_cat(
+    _: Authorized<CatsWrite>,
+    State(app_state): State<AppState>,
+    Path(cool_cat_club_id): Path<Uuid>,
+    Json(patch): Json<Value>,
+) -> Result<(StatusCode, Json<Cat>)> {
+    // lock the row so concurrent patches don't clobber each other
+    let mut conn = app_state.db.acquire().await?;
+    let mut tx = conn.begin().await?;
+
+    let cat = Cat::lock_in_db(&mut *tx, cool_cat_club_id)
+        .await?
+        .ok_or(Error::NotFoundError)?;
+
+    // apply the patch to the json form of the cat, then turn it back into a cat
+    let mut target = serde_json::to_value(&cat).context("serialize cat")?;
+    merge_patch(&mut target, patch);
+    let cat: Cat = serde_json::from_value(target)
+        .map_err(|e| Error::UnprocessableEntityError(e.to_string()))?;
+
+    if cat.cool_cat_club_id != cool_cat_club_id {
+        return Err(Error::BadRequestError(
+            "cool_cat_club_id cannot be changed".to_string(),
+        ));
+    }
+
+    cat.validate()?;
+    cat.update_in_db(&mut *tx).await?;
+    tx.commit().await?;
+
+    Ok((StatusCode::OK, Json(cat)))
+}
+
+/// RFC 7396 section 2: objects are merged recursively, `null` removes a member
+/// and anything else replaces the target outright
+fn merge_patch(target: &mut Value, patch: Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch;
+        return;
+    };
+
+    if !target.is_object() {
+        *target = Value::Object(Default::default());
+    }
+
+    let Value::Object(target) = target else {
+        unreachable!()
+    };
+
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(&key);
+        } else {
+            merge_patch(target.entry(key).or_insert(Value::Null), value);
+        }
+    }
+}
@@ -1,13 +1,33 @@
 use crate::app::AppState;
-use crate::error::Result;
+use crate::auth::{Authorized, CatsWrite};
+use crate::error::{PROBLEM_CONTENT_TYPE, Problem, Result};
+use crate::extract::Json;
 use crate::types::v1::types::Cat;
-use axum::{Json, extract::State, http::StatusCode};
+use axum::{extract::State, http::StatusCode};
 
+#[utoipa::path(
+    post,
+    path = "/v1/cats",
+    tag = "cats",
+    security(("bearer" = ["cats:write"])),
+    request_body = Cat,
+    responses(
+        (status = CREATED, description = "The cat was added", body = Cat),
+        (status = CONFLICT, description = "A cat with this id already exists", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = UNPROCESSABLE_ENTITY, description = "The cat failed validation", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = UNAUTHORIZED, description = "Missing or invalid api key or token", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = SERVICE_UNAVAILABLE, description = "The token's signing keys can't be fetched, retry after `Retry-After` seconds", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = FORBIDDEN, description = "The api key or token lacks the cats:write scope", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = TOO_MANY_REQUESTS, description = "Over the rate limit, retry after `Retry-After` seconds", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+    )
+)]
 pub async fn create_cat(
+    _: Authorized<CatsWrite>,
     State(app_state): State<AppState>,
     Json(cat): Json<Cat>,
 ) -> Result<(StatusCode, Json<Cat>)> {
-    cat.write_to_db(&app_state.db).await?;
+    cat.validate()?;
+    cat.write_to_db(&mut *app_state.db.acquire().await?).await?;
 
     // a little wasteful we reserialize, but ok for this
     Ok((StatusCode::CREATED, Json(cat)))
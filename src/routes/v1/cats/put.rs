@@ -0,0 +1,45 @@
+use crate::app::AppState;
+use crate::auth::{Authorized, CatsWrite};
+use crate::error::{Error, PROBLEM_CONTENT_TYPE, Problem, Result};
+use crate::extract::{Json, Path};
+use crate::types::v1::types::Cat;
+use axum::{extract::State, http::StatusCode};
+use uuid::Uuid;
+
+#[utoipa::path(
+    put,
+    path = "/v1/cats/{cool_cat_club_id}",
+    tag = "cats",
+    security(("bearer" = ["cats:write"])),
+    params(("cool_cat_club_id" = Uuid, Path)),
+    request_body = Cat,
+    responses(
+        (status = OK, description = "The cat was replaced", body = Cat),
+        (status = BAD_REQUEST, description = "The body's id doesn't match the path", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = NOT_FOUND, description = "No cat with this id", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = UNPROCESSABLE_ENTITY, description = "The cat failed validation", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = UNAUTHORIZED, description = "Missing or invalid api key or token", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = SERVICE_UNAVAILABLE, description = "The token's signing keys can't be fetched, retry after `Retry-After` seconds", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = FORBIDDEN, description = "The api key or token lacks the cats:write scope", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = TOO_MANY_REQUESTS, description = "Over the rate limit, retry after `Retry-After` seconds", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+    )
+)]
+pub async fn replace_cat(
+    _: Authorized<CatsWrite>,
+    State(app_state): State<AppState>,
+    Path(cool_cat_club_id): Path<Uuid>,
+    Json(cat): Json<Cat>,
+) -> Result<(StatusCode, Json<Cat>)> {
+    // the id in the path is the one being replaced, the body can't move it
+    if cat.cool_cat_club_id != cool_cat_club_id {
+        return Err(Error::BadRequestError(
+            "cool_cat_club_id in body does not match path".to_string(),
+        ));
+    }
+
+    cat.validate()?;
+    cat.update_in_db(&mut *app_state.db.acquire().await?)
+        .await?;
+
+    Ok((StatusCode::OK, Json(cat)))
+}
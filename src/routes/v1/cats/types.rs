@@ -1,16 +1,26 @@
-use crate::error::Result;
+use crate::error::{Error, FieldError, Result};
 use serde::{Deserialize, Serialize};
 use sqlx::prelude::FromRow;
+use sqlx::{PgExecutor, Postgres, QueryBuilder};
+use std::str::FromStr;
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
-#[derive(sqlx::Type, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[derive(sqlx::Type, Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, ToSchema)]
 #[sqlx(type_name = "eye_color")]
 pub enum EyeColor {
     Blue,
     Brown,
 }
 
-#[derive(Serialize, Deserialize, Debug, FromRow, PartialEq, Eq)]
+/// Longest name we accept, in characters
+pub const MAX_NAME_LEN: usize = 64;
+
+/// Oldest age we accept, the oldest cat on record was 38 but ours don't live
+/// that long
+pub const MAX_AGE: i16 = 30;
+
+#[derive(Serialize, Deserialize, Debug, FromRow, PartialEq, Eq, ToSchema)]
 pub struct Cat {
     pub name: String,
     pub cool_cat_club_id: Uuid,
@@ -19,7 +29,86 @@ pub struct Cat {
 }
 
 impl Cat {
-    pub async fn write_to_db(&self, pool: &sqlx::PgPool) -> Result<()> {
+    /// Checks every field against the domain rules, reporting all failures at
+    /// once. These are mirrored as CHECK constraints on the cats table, so keep
+    /// the two in sync.
+    pub fn validate(&self) -> Result<()> {
+        let mut errors = Vec::new();
+
+        let name_len = self.name.chars().count();
+        if name_len == 0 || name_len > MAX_NAME_LEN {
+            errors.push(FieldError::new(
+                "name",
+                format!("must be between 1 and {MAX_NAME_LEN} characters"),
+            ));
+        } else if self.name.trim() != self.name {
+            errors.push(FieldError::new(
+                "name",
+                "must not start or end with whitespace",
+            ));
+        } else if !self
+            .name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, ' ' | '\'' | '-' | '.'))
+        {
+            errors.push(FieldError::new(
+                "name",
+                "may only contain ascii letters and digits, spaces, apostrophes, hyphens and periods",
+            ));
+        }
+
+        if !(0..=MAX_AGE).contains(&self.age) {
+            errors.push(FieldError::new(
+                "age",
+                format!("must be between 0 and {MAX_AGE}"),
+            ));
+        }
+
+        if self.cool_cat_club_id.is_nil() {
+            errors.push(FieldError::new(
+                "cool_cat_club_id",
+                "must not be the nil UUID",
+            ));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::ValidationError(errors))
+        }
+    }
+
+    /// The cat with the given `cool_cat_club_id`, if there is one
+    #[tracing::instrument(name = "select cat", skip(executor))]
+    pub async fn find_in_db(
+        executor: impl PgExecutor<'_>,
+        cool_cat_club_id: Uuid,
+    ) -> Result<Option<Self>> {
+        let cat = sqlx::query_as("SELECT * FROM cats WHERE cool_cat_club_id = $1")
+            .bind(cool_cat_club_id)
+            .fetch_optional(executor)
+            .await?;
+
+        Ok(cat)
+    }
+
+    /// Like [`Cat::find_in_db`], but the row stays locked until the
+    /// transaction `executor` is part of ends
+    #[tracing::instrument(name = "select cat for update", skip(executor))]
+    pub async fn lock_in_db(
+        executor: impl PgExecutor<'_>,
+        cool_cat_club_id: Uuid,
+    ) -> Result<Option<Self>> {
+        let cat = sqlx::query_as("SELECT * FROM cats WHERE cool_cat_club_id = $1 FOR UPDATE")
+            .bind(cool_cat_club_id)
+            .fetch_optional(executor)
+            .await?;
+
+        Ok(cat)
+    }
+
+    #[tracing::instrument(name = "insert cat", skip_all, fields(cool_cat_club_id = %self.cool_cat_club_id))]
+    pub async fn write_to_db(&self, executor: impl PgExecutor<'_>) -> Result<()> {
         let query = r#"
             INSERT INTO cats (name, cool_cat_club_id, age, eye_color)
             VALUES ($1, $2, $3, $4)
@@ -29,10 +118,194 @@ impl Cat {
             .bind(&self.name)
             .bind(self.cool_cat_club_id)
             .bind(self.age)
-            .bind(&self.eye_color)
-            .execute(pool)
+            .bind(self.eye_color)
+            .execute(executor)
+            .await
+            .map_err(|e| match e {
+                sqlx::Error::Database(db) if db.is_unique_violation() => {
+                    Error::ConflictError(format!(
+                        "cat with cool_cat_club_id {} already exists",
+                        self.cool_cat_club_id
+                    ))
+                }
+                e => e.into(),
+            })?;
+
+        Ok(())
+    }
+
+    /// Overwrites the stored cat with the same `cool_cat_club_id`, failing with
+    /// `Error::NotFoundError` if there isn't one
+    #[tracing::instrument(name = "update cat", skip_all, fields(cool_cat_club_id = %self.cool_cat_club_id))]
+    pub async fn update_in_db(&self, executor: impl PgExecutor<'_>) -> Result<()> {
+        let query = r#"
+            UPDATE cats
+            SET name = $1, age = $3, eye_color = $4
+            WHERE cool_cat_club_id = $2
+        "#;
+
+        let result = sqlx::query(query)
+            .bind(&self.name)
+            .bind(self.cool_cat_club_id)
+            .bind(self.age)
+            .bind(self.eye_color)
+            .execute(executor)
             .await?;
 
+        if result.rows_affected() == 0 {
+            return Err(Error::NotFoundError);
+        }
+
+        Ok(())
+    }
+
+    /// Removes the cat with the given `cool_cat_club_id`, failing with
+    /// `Error::NotFoundError` if there isn't one
+    #[tracing::instrument(name = "delete cat", skip(executor))]
+    pub async fn delete_from_db(
+        executor: impl PgExecutor<'_>,
+        cool_cat_club_id: Uuid,
+    ) -> Result<()> {
+        let result = sqlx::query("DELETE FROM cats WHERE cool_cat_club_id = $1")
+            .bind(cool_cat_club_id)
+            .execute(executor)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(Error::NotFoundError);
+        }
+
         Ok(())
     }
 }
+
+/// Query parameters for narrowing and ordering a cat listing
+#[derive(Deserialize, Debug, Default, IntoParams)]
+#[serde(deny_unknown_fields)]
+#[into_params(parameter_in = Query)]
+pub struct CatFilter {
+    pub eye_color: Option<EyeColor>,
+    pub min_age: Option<i16>,
+    pub max_age: Option<i16>,
+    pub name_prefix: Option<String>,
+    /// comma separated `name`, `age` or `eye_color`, prefix with `-` for descending
+    #[serde(default)]
+    #[param(value_type = Option<String>, example = "age,-name")]
+    pub sort: Sort,
+}
+
+impl CatFilter {
+    /// Every matching cat, in the requested order
+    #[tracing::instrument(name = "select cats", skip(executor))]
+    pub async fn fetch_from_db(&self, executor: impl PgExecutor<'_>) -> Result<Vec<Cat>> {
+        let mut builder = QueryBuilder::new("SELECT * FROM cats WHERE TRUE");
+        self.push_conditions(&mut builder);
+        self.sort.push_order_by(&mut builder);
+
+        Ok(builder.build_query_as::<Cat>().fetch_all(executor).await?)
+    }
+
+    /// Pushes an ` AND <condition>` for every filter that is set, so the builder
+    /// must already be inside a `WHERE` clause
+    pub fn push_conditions(&self, builder: &mut QueryBuilder<'_, Postgres>) {
+        if let Some(eye_color) = self.eye_color {
+            builder.push(" AND eye_color = ").push_bind(eye_color);
+        }
+
+        if let Some(min_age) = self.min_age {
+            builder.push(" AND age >= ").push_bind(min_age);
+        }
+
+        if let Some(max_age) = self.max_age {
+            builder.push(" AND age <= ").push_bind(max_age);
+        }
+
+        // starts_with saves us from escaping LIKE wildcards in user input
+        if let Some(name_prefix) = &self.name_prefix {
+            builder
+                .push(" AND starts_with(name, ")
+                .push_bind(name_prefix.clone())
+                .push(")");
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortField {
+    Name,
+    Age,
+    EyeColor,
+}
+
+impl SortField {
+    pub fn column(&self) -> &'static str {
+        match self {
+            SortField::Name => "name",
+            SortField::Age => "age",
+            SortField::EyeColor => "eye_color",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortKey {
+    pub field: SortField,
+    pub descending: bool,
+}
+
+/// Comma separated sort keys, most significant first, with a leading `-` for
+/// descending order e.g. `age,-name`. The id is always appended as a final
+/// tie-breaker so the order is total.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub struct Sort(pub Vec<SortKey>);
+
+impl Sort {
+    pub fn push_order_by(&self, builder: &mut QueryBuilder<'_, Postgres>) {
+        builder.push(" ORDER BY ");
+        for key in &self.0 {
+            builder.push(key.field.column());
+            builder.push(if key.descending { " DESC, " } else { " ASC, " });
+        }
+        builder.push("cool_cat_club_id ASC");
+    }
+}
+
+impl FromStr for Sort {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut keys: Vec<SortKey> = Vec::new();
+
+        for raw in s.split(',') {
+            let (name, descending) = match raw.strip_prefix('-') {
+                Some(name) => (name, true),
+                None => (raw, false),
+            };
+
+            let field = match name {
+                "name" => SortField::Name,
+                "age" => SortField::Age,
+                "eye_color" => SortField::EyeColor,
+                "" => return Err(format!("empty sort key in `{s}`")),
+                other => return Err(format!("unknown sort key `{other}`")),
+            };
+
+            if keys.iter().any(|k| k.field == field) {
+                return Err(format!("duplicate sort key `{name}`"));
+            }
+
+            keys.push(SortKey { field, descending });
+        }
+
+        Ok(Self(keys))
+    }
+}
+
+impl TryFrom<String> for Sort {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        value.parse()
+    }
+}
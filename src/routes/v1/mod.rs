@@ -1,5 +1,5 @@
 // don't expose anything we don't need to
-mod cats;
+pub(crate) mod cats;
 
 // crate will need access to these
 pub(crate) mod router;
@@ -1,8 +1,11 @@
 use crate::{
     app::AppState,
     routes::v1::cats::{
+        delete::delete_cat,
         get::{get_all_cats, get_cat},
+        patch::update_cat,
         post::create_cat,
+        put::replace_cat,
     },
 };
 use axum::{Router, routing::get};
@@ -10,5 +13,11 @@ use axum::{Router, routing::get};
 pub fn get_v1_router() -> Router<AppState> {
     Router::new()
         .route("/cats", get(get_all_cats).post(create_cat))
-        .route("/cats/{cool_cat_club_id}", get(get_cat))
+        .route(
+            "/cats/{cool_cat_club_id}",
+            get(get_cat)
+                .put(replace_cat)
+                .patch(update_cat)
+                .delete(delete_cat),
+        )
 }
@@ -0,0 +1,95 @@
+use crate::{
+    app::AppState,
+    auth::{Authorized, CatsRead},
+    error::{Error, PROBLEM_CONTENT_TYPE, Problem, Result},
+    extract::{Json, Query},
+    types::v2::types::{CatPage, Cursor, PageQuery, fetch_page},
+};
+use anyhow::Context;
+use axum::{
+    extract::{OriginalUri, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header::LINK},
+};
+
+#[utoipa::path(
+    get,
+    path = "/v2/cats",
+    tag = "cats",
+    security((), ("bearer" = ["cats:read"])),
+    params(PageQuery),
+    responses(
+        (
+            status = OK,
+            description = "A page of matching cats",
+            body = CatPage,
+            headers(("Link" = String, description = "RFC 8288 link to the next page, if any"))
+        ),
+        (status = BAD_REQUEST, description = "Unknown or malformed query parameter", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = UNAUTHORIZED, description = "Missing or invalid api key or token", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = SERVICE_UNAVAILABLE, description = "The token's signing keys can't be fetched, retry after `Retry-After` seconds", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = FORBIDDEN, description = "The api key or token lacks the cats:read scope", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+        (status = TOO_MANY_REQUESTS, description = "Over the rate limit, retry after `Retry-After` seconds", body = Problem, content_type = PROBLEM_CONTENT_TYPE),
+    )
+)]
+pub async fn get_cat_page(
+    _: Authorized<CatsRead>,
+    State(app_state): State<AppState>,
+    OriginalUri(uri): OriginalUri,
+    Query(query): Query<PageQuery>,
+) -> Result<(StatusCode, HeaderMap, Json<CatPage>)> {
+    // clamp the page size to what the server allows
+    let max_page_size = app_state.application.max_page_size;
+    let limit = match query.limit {
+        Some(0) => {
+            return Err(Error::BadRequestError(
+                "limit must be greater than 0".to_string(),
+            ));
+        }
+        Some(limit) => limit.min(max_page_size),
+        None => max_page_size,
+    };
+
+    let cursor = query.cursor.as_deref().map(Cursor::decode).transpose()?;
+    let filter = query.filter();
+
+    // grab one extra so we know whether there is another page
+    let mut cats = fetch_page(
+        &mut *app_state.db.acquire().await?,
+        &filter,
+        cursor.as_ref(),
+        i64::from(limit) + 1,
+    )
+    .await?;
+
+    let next_cursor = if cats.len() > limit as usize {
+        cats.truncate(limit as usize);
+        cats.last()
+            .map(|cat| Cursor::from(cat).encode())
+            .transpose()?
+    } else {
+        None
+    };
+
+    // RFC 8288 link to the next page, keeping whatever filters the client sent
+    let mut headers = HeaderMap::new();
+    if let Some(next_cursor) = &next_cursor {
+        let mut params: Vec<&str> = uri
+            .query()
+            .unwrap_or_default()
+            .split('&')
+            .filter(|p| !p.is_empty() && !p.starts_with("limit=") && !p.starts_with("cursor="))
+            .collect();
+        let limit = format!("limit={limit}");
+        let cursor = format!("cursor={next_cursor}");
+        params.push(&limit);
+        params.push(&cursor);
+
+        let link = format!(r#"<{}?{}>; rel="next""#, uri.path(), params.join("&"));
+        headers.insert(
+            LINK,
+            HeaderValue::from_str(&link).context("build link header")?,
+        );
+    }
+
+    Ok((StatusCode::OK, headers, Json(CatPage { cats, next_cursor })))
+}
@@ -0,0 +1,143 @@
+use crate::error::{Error, Result};
+use crate::types::v1::types::{Cat, CatFilter, EyeColor, Sort, SortField, SortKey};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgExecutor, Postgres, QueryBuilder};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+/// Same filters as the v1 listing plus the paging controls. Spelled out
+/// rather than flattened since serde can't combine `flatten` with
+/// `deny_unknown_fields`.
+#[derive(Deserialize, Debug, Default, IntoParams)]
+#[serde(deny_unknown_fields)]
+#[into_params(parameter_in = Query)]
+pub struct PageQuery {
+    /// page size, clamped to the server maximum
+    pub limit: Option<u32>,
+    /// `next_cursor` from the previous page
+    pub cursor: Option<String>,
+    pub eye_color: Option<EyeColor>,
+    pub min_age: Option<i16>,
+    pub max_age: Option<i16>,
+    pub name_prefix: Option<String>,
+    /// comma separated `name`, `age` or `eye_color`, prefix with `-` for descending
+    #[serde(default)]
+    #[param(value_type = Option<String>, example = "age,-name")]
+    pub sort: Sort,
+}
+
+impl PageQuery {
+    pub fn filter(&self) -> CatFilter {
+        CatFilter {
+            eye_color: self.eye_color,
+            min_age: self.min_age,
+            max_age: self.max_age,
+            name_prefix: self.name_prefix.clone(),
+            sort: self.sort.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct CatPage {
+    pub cats: Vec<Cat>,
+    /// pass back as `cursor` to get the page after this one, `None` on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// Up to `limit` cats matching `filter`, starting after `cursor` if given
+#[tracing::instrument(name = "select cat page", skip(executor))]
+pub async fn fetch_page(
+    executor: impl PgExecutor<'_>,
+    filter: &CatFilter,
+    cursor: Option<&Cursor>,
+    limit: i64,
+) -> Result<Vec<Cat>> {
+    let mut builder = QueryBuilder::new("SELECT * FROM cats WHERE TRUE");
+    filter.push_conditions(&mut builder);
+    if let Some(cursor) = cursor {
+        cursor.push_after(&filter.sort, &mut builder);
+    }
+    filter.sort.push_order_by(&mut builder);
+    builder.push(" LIMIT ").push_bind(limit);
+
+    Ok(builder.build_query_as::<Cat>().fetch_all(executor).await?)
+}
+
+/// Position in the cat listing, i.e. the sort key of the last cat on the
+/// previous page. Clients only ever see the encoded form, so what we page on
+/// can change without breaking them. A cursor is only meaningful alongside the
+/// same `sort` it was issued for.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Cursor {
+    pub name: String,
+    pub age: i16,
+    pub eye_color: EyeColor,
+    pub cool_cat_club_id: Uuid,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> Result<String> {
+        let json = serde_json::to_vec(self).map_err(anyhow::Error::from)?;
+        Ok(URL_SAFE_NO_PAD.encode(json))
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self> {
+        let bad_cursor = || Error::BadRequestError("malformed cursor".to_string());
+
+        let bytes = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| bad_cursor())?;
+        serde_json::from_slice(&bytes).map_err(|_| bad_cursor())
+    }
+
+    /// Pushes an ` AND (...)` that only lets through rows ordered strictly
+    /// after this cursor under `sort`. With mixed sort directions a row
+    /// comparison won't do, so this expands to
+    /// `(k1 > v1) OR (k1 = v1 AND k2 < v2) OR ... OR (k1 = v1 AND ... AND id > vid)`
+    pub fn push_after(&self, sort: &Sort, builder: &mut QueryBuilder<'_, Postgres>) {
+        builder.push(" AND (");
+
+        for (i, key) in sort.0.iter().enumerate() {
+            self.push_equal_prefix(&sort.0[..i], builder);
+            builder.push(key.field.column());
+            builder.push(if key.descending { " < " } else { " > " });
+            self.push_value(key.field, builder);
+            builder.push(") OR ");
+        }
+
+        self.push_equal_prefix(&sort.0, builder);
+        builder
+            .push("cool_cat_club_id > ")
+            .push_bind(self.cool_cat_club_id)
+            .push("))");
+    }
+
+    fn push_equal_prefix(&self, keys: &[SortKey], builder: &mut QueryBuilder<'_, Postgres>) {
+        builder.push("(");
+        for key in keys {
+            builder.push(key.field.column()).push(" = ");
+            self.push_value(key.field, builder);
+            builder.push(" AND ");
+        }
+    }
+
+    fn push_value(&self, field: SortField, builder: &mut QueryBuilder<'_, Postgres>) {
+        match field {
+            SortField::Name => builder.push_bind(self.name.clone()),
+            SortField::Age => builder.push_bind(self.age),
+            SortField::EyeColor => builder.push_bind(self.eye_color),
+        };
+    }
+}
+
+impl From<&Cat> for Cursor {
+    fn from(cat: &Cat) -> Self {
+        Self {
+            name: cat.name.clone(),
+            age: cat.age,
+            eye_color: cat.eye_color,
+            cool_cat_club_id: cat.cool_cat_club_id,
+        }
+    }
+}
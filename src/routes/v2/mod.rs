@@ -0,0 +1,6 @@
+// don't expose anything we don't need to
+pub(crate) mod cats;
+
+// crate will need access to these
+pub(crate) mod router;
+pub use cats::types;
@@ -0,0 +1,6 @@
+use crate::{app::AppState, routes::v2::cats::get::get_cat_page};
+use axum::{Router, routing::get};
+
+pub fn get_v2_router() -> Router<AppState> {
+    Router::new().route("/cats", get(get_cat_page))
+}
@@ -1,48 +1,142 @@
 use crate::{
+    api_keys::keys,
     app::App,
-    settings::get_settings,
-    telemetry::{get_subscriber, init_subscriber},
+    cli::{Cli, CliCommand, KeysCommand, MigrateCommand},
+    db::connect,
+    error::{Error, RunError},
+    migrate::migrate,
+    settings::{Settings, get_settings_from},
+    telemetry::{get_subscriber_with_writer, init_subscriber},
 };
-use tracing::error;
+use anyhow::{Context, anyhow};
+use tracing::{error, info};
+use tracing_subscriber::fmt::MakeWriter;
 
-pub async fn run() {
+/// Does what `cli` asks until it's done or, for `serve`, until shut down.
+/// Failures are reported here, the caller only needs [`RunError::exit_code`].
+pub async fn run(cli: Cli) -> Result<(), RunError> {
     // get the settings
-    let settings = match get_settings() {
-        Ok(s) => s,
-        Err(e) => {
-            println!("Error getting settings: {e:?}");
-            return;
-        }
-    };
+    let settings =
+        get_settings_from(cli.config_dir.as_deref(), cli.env.as_deref()).map_err(|e| {
+            // no tracing yet, stderr is all we have
+            eprintln!("Error getting settings: {e:?}");
+            RunError::Config(e.into())
+        })?;
 
-    // initialize tracing
-    let subscriber = match get_subscriber() {
-        Ok(s) => s,
-        Err(e) => {
-            println!("Error getting subscriber: {e:?}");
-            return;
+    match cli.command {
+        // nothing to log, and stdout is for the settings
+        CliCommand::ConfigCheck => config_check(&settings),
+        CliCommand::Serve => with_telemetry(settings, std::io::stdout, serve).await,
+        // these print their output on stdout, so their logs can't go there too
+        CliCommand::Migrate(command) => {
+            with_telemetry(settings, std::io::stderr, |settings| {
+                run_migrate(command, settings)
+            })
+            .await
         }
+        CliCommand::Keys(command) => {
+            with_telemetry(settings, std::io::stderr, |settings| {
+                run_keys(command, settings)
+            })
+            .await
+        }
+    }
+}
+
+/// Validates `settings` and sets up tracing with logs going to `make_writer`,
+/// then runs `command` with them, logging how it failed if it did
+async fn with_telemetry<W, F, Fut>(
+    settings: Settings,
+    make_writer: W,
+    command: F,
+) -> Result<(), RunError>
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+    F: FnOnce(Settings) -> Fut,
+    Fut: Future<Output = Result<(), RunError>>,
+{
+    validate(&settings)?;
+
+    // initialize tracing, the guard flushes any spans left when we exit
+    let telemetry_error = |e: Error| {
+        eprintln!("Error initializing telemetry: {e:?}");
+        RunError::Telemetry(e.into())
     };
+    let (subscriber, _telemetry_guard) =
+        get_subscriber_with_writer(&settings.logging, &settings.otlp, make_writer)
+            .map_err(telemetry_error)?;
+    init_subscriber(subscriber).map_err(telemetry_error)?;
+
+    let result = command(settings).await;
 
-    if let Err(e) = init_subscriber(subscriber) {
-        println!("Error initializing subscriber {e:?}");
-        return;
+    if let Err(e) = &result {
+        error!("Error running app: {e:?}");
     }
 
+    result
+}
+
+async fn serve(settings: Settings) -> Result<(), RunError> {
     // build app
-    let app = match App::build(settings).await {
-        Ok(a) => a,
-        Err(e) => {
-            error!("Error building app: {e:?}");
-            return;
-        }
-    };
+    let app = App::build(settings).await?;
 
     // run app
-    match app.run_until_stopped().await {
-        Ok(_) => unreachable!(),
-        Err(e) => {
-            error!("Error running app: {e:?}");
-        }
+    app.run_until_stopped()
+        .await
+        .map_err(|e| RunError::Serve(e.into()))?;
+
+    info!("shut down cleanly");
+    Ok(())
+}
+
+async fn run_migrate(command: MigrateCommand, settings: Settings) -> Result<(), RunError> {
+    let db = connect(&settings.db)
+        .await
+        .context("connect to db")
+        .map_err(RunError::DbConnect)?;
+
+    let result = migrate(command, &db).await.map_err(RunError::Migrate);
+    db.close().await;
+
+    result
+}
+
+async fn run_keys(command: KeysCommand, settings: Settings) -> Result<(), RunError> {
+    let db = connect(&settings.db)
+        .await
+        .context("connect to db")
+        .map_err(RunError::DbConnect)?;
+
+    let result = keys(command, &db).await.map_err(RunError::Keys);
+    db.close().await;
+
+    result
+}
+
+/// Prints the settings as json with secrets redacted, then every problem found
+/// with them
+fn config_check(settings: &Settings) -> Result<(), RunError> {
+    let json = serde_json::to_string_pretty(settings)
+        .context("serialize settings")
+        .map_err(RunError::Config)?;
+    println!("{json}");
+
+    validate(settings)
+}
+
+/// Reports every invalid setting on stderr, there's no tracing yet
+fn validate(settings: &Settings) -> Result<(), RunError> {
+    let errors = settings.validate();
+    for e in &errors {
+        eprintln!("invalid setting {e}");
     }
+
+    if !errors.is_empty() {
+        return Err(RunError::Config(anyhow!(
+            "{} invalid setting(s)",
+            errors.len()
+        )));
+    }
+
+    Ok(())
 }
@@ -0,0 +1,81 @@
+use crate::settings::HttpSettings;
+use axum::Router;
+use axum::extract::ConnectInfo;
+use axum::serve::Listener;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto::Builder;
+use hyper_util::server::graceful::GracefulShutdown;
+use hyper_util::service::TowerToHyperService;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::task::JoinSet;
+use tower_http::add_extension::AddExtension;
+use tracing::debug;
+
+/// Serves `router` on every connection `listener` hands out, speaking http/1.1
+/// or http/2 to each depending on what the client opens with. Once `signal`
+/// resolves no new connections are accepted, and this returns when the open
+/// ones have finished their requests. Dropping it before then aborts whatever
+/// connections are still open. Handlers can find the peer's address in a
+/// `ConnectInfo<SocketAddr>`.
+pub async fn serve<L>(
+    mut listener: L,
+    router: Router,
+    settings: &HttpSettings,
+    signal: impl Future<Output = ()>,
+) where
+    L: Listener<Addr = SocketAddr>,
+{
+    let builder = builder(settings);
+    let graceful = GracefulShutdown::new();
+    // aborts every connection when dropped, as when the drain deadline passes
+    let mut connections = JoinSet::new();
+    tokio::pin!(signal);
+
+    loop {
+        let (io, addr) = tokio::select! {
+            conn = listener.accept() => conn,
+            // reap finished connections so the set doesn't keep growing
+            Some(_) = connections.join_next(), if !connections.is_empty() => continue,
+            _ = &mut signal => break,
+        };
+
+        let conn = builder
+            .serve_connection_with_upgrades(
+                TokioIo::new(io),
+                TowerToHyperService::new(AddExtension::new(router.clone(), ConnectInfo(addr))),
+            )
+            .into_owned();
+        let conn = graceful.watch(conn);
+        connections.spawn(async move {
+            if let Err(e) = conn.await {
+                debug!("connection with {addr} failed: {e}");
+            }
+        });
+    }
+
+    // stop accepting before waiting on whoever is still connected
+    drop(listener);
+    graceful.shutdown().await;
+}
+
+fn builder(settings: &HttpSettings) -> Builder<TokioExecutor> {
+    let mut builder = Builder::new(TokioExecutor::new());
+
+    builder
+        .http1()
+        .keep_alive(settings.http1_keep_alive)
+        .timer(TokioTimer::new());
+
+    // pings go unanswered through some proxies, so they can be turned off
+    let keep_alive_interval = (settings.http2_keep_alive_interval_ms != 0)
+        .then(|| Duration::from_millis(settings.http2_keep_alive_interval_ms));
+    builder
+        .http2()
+        .max_concurrent_streams(settings.http2_max_concurrent_streams)
+        .keep_alive_interval(keep_alive_interval)
+        .keep_alive_timeout(Duration::from_millis(settings.http2_keep_alive_timeout_ms))
+        .timer(TokioTimer::new());
+
+    builder
+}
@@ -1,23 +1,88 @@
 use crate::error::Result;
-use anyhow::Context;
-use config::Config;
+use anyhow::{Context, anyhow};
+use config::{Config, Map, Source, Value, ValueKind};
+use ipnet::IpNet;
+use percent_encoding::percent_decode_str;
 use secrecy::{ExposeSecret, SecretString};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize, Serializer};
 use serde_aux::field_attributes::deserialize_number_from_string;
-use sqlx::postgres::{PgConnectOptions, PgSslMode};
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions, PgSslMode};
+use std::fmt;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use tracing::info;
+use url::Url;
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Settings {
+    /// which `configuration/{environment}.yaml` was layered on top of the base
+    #[serde(skip_deserializing)]
+    pub environment: String,
     pub application: ApplicationSettings,
     pub db: DbSettings,
+    pub metrics: MetricsSettings,
+    pub otlp: OtlpSettings,
+    pub logging: LoggingSettings,
+    pub auth: AuthSettings,
+    pub rate_limit: RateLimitSettings,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct ApplicationSettings {
     pub host: String,
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
+    /// upper bound on the number of items a paginated endpoint returns at once
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_page_size: u32,
+    /// serve a rendered version of `/openapi.json` at `/docs`
+    pub docs_ui: bool,
+    /// how long each `/health/ready` check gets before it counts as failed
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub readiness_timeout_ms: u64,
+    /// how long to keep serving after shutdown starts, with readiness already
+    /// failing, so load balancers stop sending us traffic first
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub shutdown_delay_ms: u64,
+    /// how long in-flight requests get to finish once we stop accepting new
+    /// connections, anything left after that is cut off
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub drain_timeout_ms: u64,
+    pub tls: TlsSettings,
+    pub http: HttpSettings,
+}
+
+/// Serving https ourselves, for when there's no proxy in front to do it
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct TlsSettings {
+    pub enabled: bool,
+    /// PEM certificate chain, leaf first
+    #[serde(default)]
+    pub cert_path: PathBuf,
+    /// PEM private key for the leaf certificate
+    #[serde(default)]
+    pub key_path: PathBuf,
+    /// how often to check the files for a renewed certificate
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub reload_interval_ms: u64,
+}
+
+/// Tuning for the connections themselves, http/2 is spoken both over tls and
+/// in cleartext to clients that know to expect it
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct HttpSettings {
+    /// reuse http/1.1 connections for more than one request
+    pub http1_keep_alive: bool,
+    /// how many requests a client may have in flight on one http/2 connection
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub http2_max_concurrent_streams: u32,
+    /// how often to ping idle http/2 connections, 0 never pings
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub http2_keep_alive_interval_ms: u64,
+    /// how long to wait for a ping to be answered before closing the connection
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub http2_keep_alive_timeout_ms: u64,
 }
 
 impl ApplicationSettings {
@@ -26,54 +91,736 @@ impl ApplicationSettings {
     }
 }
 
-#[derive(Deserialize, Debug, Clone)]
+/// Who may call the API. Writes always need an api key, see `gha_demo keys`,
+/// or a JWT when `jwt` is enabled.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct AuthSettings {
+    /// let anyone read cats, a token that's sent anyway must still be valid
+    pub public_reads: bool,
+    pub jwt: JwtSettings,
+}
+
+/// Accepting JWTs signed by the SSO, with the scopes they carry
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct JwtSettings {
+    pub enabled: bool,
+    /// the `iss` tokens must have
+    #[serde(default)]
+    pub issuer: String,
+    /// the `aud` tokens must include
+    #[serde(default)]
+    pub audience: String,
+    /// where the signing keys are published, set this or `jwks_path`
+    pub jwks_url: Option<String>,
+    /// a local copy of the signing keys, for offline use
+    pub jwks_path: Option<PathBuf>,
+    /// how long fetched keys are trusted before fetching them again
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub jwks_refresh_interval_ms: u64,
+    /// how soon a token signed by an unknown key may trigger another fetch
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub jwks_min_refresh_interval_ms: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub jwks_timeout_ms: u64,
+    /// clock skew allowed when checking `exp` and `nbf`
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub leeway_secs: u64,
+}
+
+/// Token buckets per client and route group, a client is its api key if it
+/// sent a valid one and its ip otherwise. Off unless `enabled`, and behind a
+/// proxy `trusted_proxies` must be set or every client shares the proxy's ip.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct RateLimitSettings {
+    pub enabled: bool,
+    pub backend: RateLimitBackend,
+    /// ips and cidrs of the proxies in front of us, whose `X-Forwarded-For`
+    /// is believed, either a list or comma separated
+    #[serde(deserialize_with = "deserialize_list")]
+    pub trusted_proxies: Vec<String>,
+    /// `/v1/cats`, `/v2/cats` and everything under them
+    pub cats: RouteLimit,
+    pub latency: RouteLimit,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RateLimitBackend {
+    /// buckets live in this process, each replica limits on its own
+    Memory,
+    /// buckets live in the db, shared by every replica
+    Postgres,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+pub struct RouteLimit {
+    /// how many requests a client can make at once, the size of its bucket
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub burst: u32,
+    /// how fast the bucket refills
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub per_second: f64,
+}
+
+/// The `/metrics` endpoint gets its own listener so it can stay off the public
+/// network
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct MetricsSettings {
+    pub enabled: bool,
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+}
+
+impl MetricsSettings {
+    pub fn connection_string(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct LoggingSettings {
+    pub format: LogFormat,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// multi-line human readable output, nice for local development
+    Pretty,
+    /// single line human readable output
+    Compact,
+    /// one bunyan formatted json object per line, for log pipelines
+    Json,
+}
+
+/// Exporting spans to an OpenTelemetry collector, off unless `enabled`
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct OtlpSettings {
+    pub enabled: bool,
+    /// base url of the collector, for the http protocols `/v1/traces` is
+    /// appended the same way `OTEL_EXPORTER_OTLP_ENDPOINT` does it
+    pub endpoint: String,
+    pub protocol: OtlpProtocol,
+    pub service_name: String,
+    /// fraction of new traces to record, incoming sampled traces are always kept
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub sampling_ratio: f64,
+}
+
+/// Named after the values `OTEL_EXPORTER_OTLP_PROTOCOL` takes
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtlpProtocol {
+    #[serde(rename = "grpc")]
+    Grpc,
+    #[serde(rename = "http/protobuf")]
+    HttpProtobuf,
+    #[serde(rename = "http/json")]
+    HttpJson,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct DbSettings {
     pub username: String,
+    #[serde(serialize_with = "redacted")]
     pub password: SecretString,
     pub host: String,
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
     pub database: String,
-    pub ssl: bool,
+    /// how hard to insist on tls, see [`DbSettings::ssl_mode`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ssl_mode: Option<SslMode>,
+    /// older spelling of `ssl_mode`, `true` is `require` and `false` is
+    /// `disable`, ignored when `ssl_mode` is set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ssl: Option<bool>,
+    /// CA certificate(s) to verify the server against, for `verify-ca` and
+    /// `verify-full`
+    pub ssl_root_cert: Option<PathBuf>,
+    /// client certificate, for servers that authenticate clients by cert
+    pub ssl_client_cert: Option<PathBuf>,
+    pub ssl_client_key: Option<PathBuf>,
+    /// apply pending migrations when the app starts, turn this off to run
+    /// them as a separate `gha_demo migrate run` job instead
+    pub migrate_on_start: bool,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_connections: u32,
+    /// connections the pool keeps open even when idle
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_connections: u32,
+    /// how long to wait for a connection before giving up, also bounds each
+    /// connect attempt at startup
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub acquire_timeout_ms: u64,
+    /// close connections idle for this long, 0 keeps them forever
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub idle_timeout_ms: u64,
+    /// recycle connections after this long, 0 keeps them forever
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_lifetime_ms: u64,
+    /// postgres aborts statements running longer than this, 0 disables it
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub statement_timeout_ms: u64,
+    /// extra connect attempts at startup before giving up
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub connect_retries: u32,
+    /// backoff before the first retry, doubled for each one after that
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub connect_backoff_initial_ms: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub connect_backoff_max_ms: u64,
+}
+
+/// Same values and meaning as libpq's `sslmode`
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SslMode {
+    Disable,
+    Allow,
+    Prefer,
+    Require,
+    /// require tls and check the server cert is signed by a trusted CA
+    VerifyCa,
+    /// `verify-ca`, and check the cert matches the host we connected to
+    VerifyFull,
+}
+
+impl SslMode {
+    /// Whether every connection is guaranteed to be encrypted
+    pub fn is_required(self) -> bool {
+        matches!(
+            self,
+            SslMode::Require | SslMode::VerifyCa | SslMode::VerifyFull
+        )
+    }
+}
+
+impl std::str::FromStr for SslMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "disable" => Ok(SslMode::Disable),
+            "allow" => Ok(SslMode::Allow),
+            "prefer" => Ok(SslMode::Prefer),
+            "require" => Ok(SslMode::Require),
+            "verify-ca" => Ok(SslMode::VerifyCa),
+            "verify-full" => Ok(SslMode::VerifyFull),
+            other => Err(anyhow!("unknown sslmode {other}")),
+        }
+    }
+}
+
+impl From<SslMode> for PgSslMode {
+    fn from(mode: SslMode) -> Self {
+        match mode {
+            SslMode::Disable => PgSslMode::Disable,
+            SslMode::Allow => PgSslMode::Allow,
+            SslMode::Prefer => PgSslMode::Prefer,
+            SslMode::Require => PgSslMode::Require,
+            SslMode::VerifyCa => PgSslMode::VerifyCa,
+            SslMode::VerifyFull => PgSslMode::VerifyFull,
+        }
+    }
 }
 
 impl DbSettings {
-    pub fn get_db_settings(&self) -> PgConnectOptions {
-        let ssl_mode = if self.ssl {
-            PgSslMode::Require
-        } else {
-            PgSslMode::Disable
-        };
+    /// `ssl_mode` if set, then the legacy `ssl` flag, and `prefer` like libpq
+    /// when neither is
+    pub fn ssl_mode(&self) -> SslMode {
+        match (self.ssl_mode, self.ssl) {
+            (Some(mode), _) => mode,
+            (None, Some(true)) => SslMode::Require,
+            (None, Some(false)) => SslMode::Disable,
+            (None, None) => SslMode::Prefer,
+        }
+    }
 
-        PgConnectOptions::new()
+    pub fn get_db_settings(&self) -> PgConnectOptions {
+        let mut options = PgConnectOptions::new()
             .host(&self.host)
             .port(self.port)
             .username(&self.username)
             .password(self.password.expose_secret())
-            .ssl_mode(ssl_mode)
+            .ssl_mode(self.ssl_mode().into())
             .database(&self.database)
+            .options([(
+                "statement_timeout",
+                format!("{}ms", self.statement_timeout_ms),
+            )]);
+
+        if let Some(root_cert) = &self.ssl_root_cert {
+            options = options.ssl_root_cert(root_cert);
+        }
+        if let Some(client_cert) = &self.ssl_client_cert {
+            options = options.ssl_client_cert(client_cert);
+        }
+        if let Some(client_key) = &self.ssl_client_key {
+            options = options.ssl_client_key(client_key);
+        }
+
+        options
+    }
+
+    /// Takes the user, password, host, port, database and the `sslmode`,
+    /// `sslrootcert`, `sslcert` and `sslkey` params found in a `postgres://`
+    /// url, keeping the current value for anything left out. Errors never
+    /// include the url since it likely holds the password.
+    pub fn apply_database_url(&mut self, url: &str) -> Result<()> {
+        let url = Url::parse(url).map_err(|e| anyhow!("not a valid url: {e}"))?;
+        if !matches!(url.scheme(), "postgres" | "postgresql") {
+            return Err(anyhow!("expected a postgres:// url, not {}://", url.scheme()).into());
+        }
+
+        let decode = |s: &str| {
+            percent_decode_str(s)
+                .decode_utf8()
+                .map(|s| s.into_owned())
+                .map_err(|_| anyhow!("url is not valid utf-8 once decoded"))
+        };
+
+        if !url.username().is_empty() {
+            self.username = decode(url.username())?;
+        }
+        if let Some(password) = url.password() {
+            self.password = SecretString::from(decode(password)?);
+        }
+        if let Some(host) = url.host_str() {
+            self.host = host.to_string();
+        }
+        if let Some(port) = url.port() {
+            self.port = port;
+        }
+        let database = url.path().trim_start_matches('/');
+        if !database.is_empty() {
+            self.database = decode(database)?;
+        }
+
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                "sslmode" => self.ssl_mode = Some(value.parse()?),
+                "sslrootcert" => self.ssl_root_cert = Some(value.into_owned().into()),
+                "sslcert" => self.ssl_client_cert = Some(value.into_owned().into()),
+                "sslkey" => self.ssl_client_key = Some(value.into_owned().into()),
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn get_pool_options(&self) -> PgPoolOptions {
+        let non_zero = |ms| (ms > 0).then(|| Duration::from_millis(ms));
+
+        PgPoolOptions::new()
+            .max_connections(self.max_connections)
+            .min_connections(self.min_connections)
+            .acquire_timeout(Duration::from_millis(self.acquire_timeout_ms))
+            .idle_timeout(non_zero(self.idle_timeout_ms))
+            .max_lifetime(non_zero(self.max_lifetime_ms))
     }
 }
 
+/// Overrides where settings are read from
+pub const CONFIG_DIR_ENV: &str = "APP_CONFIG_DIR";
+
+/// Name of the settings directory next to the executable or under the CWD
+pub const CONFIG_DIR_NAME: &str = "configuration";
+
+/// A `postgres://` url overriding the connection parts of [`DbSettings`]
+pub const DATABASE_URL_ENV: &str = "DATABASE_URL";
+
+/// Any setting `foo` can be read from the file named by `foo_file` instead
+pub const SECRET_FILE_SUFFIX: &str = "_file";
+
+/// The environment whose settings the tests run with, the only one where
+/// binding to any free port (port 0) is allowed
+pub const TEST_ENVIRONMENT: &str = "test";
+
+/// The `base.yaml` this was built with, underneath the one on disk so every
+/// setting has a default even when that one leaves some out
+const DEFAULTS: &str = include_str!("../configuration/base.yaml");
+
 pub fn get_settings() -> Result<Settings> {
-    let environment = std::env::var("APP_ENV").unwrap_or("local".into());
+    get_settings_from(None, None)
+}
+
+/// Reads `base.yaml` and then `{environment}.yaml` from `config_dir`, with
+/// `APP_` environment variables on top. Each argument falls back to the default
+/// when `None`, see [`resolve_config_dir`], and `environment` to `APP_ENV` and
+/// then `local`.
+pub fn get_settings_from(config_dir: Option<&Path>, environment: Option<&str>) -> Result<Settings> {
+    let config_dir = match config_dir {
+        Some(config_dir) => config_dir.to_path_buf(),
+        None => resolve_config_dir()?,
+    };
+    let environment = match environment {
+        Some(environment) => environment.to_string(),
+        None => std::env::var("APP_ENV").unwrap_or("local".into()),
+    };
     info!("using the {environment} env");
 
-    let filename = format!("configuration/{}.yaml", environment);
     let settings = Config::builder()
-        .add_source(config::File::with_name("configuration/base.yaml"))
-        .add_source(config::File::with_name(&filename))
+        .add_source(config::File::from_str(DEFAULTS, config::FileFormat::Yaml))
+        .add_source(config::File::from(config_dir.join("base.yaml")))
+        .add_source(config::File::from(
+            config_dir.join(format!("{environment}.yaml")),
+        ))
         .add_source(
             config::Environment::with_prefix("APP")
                 .separator("__")
                 .prefix_separator("_"),
         )
         .build()
-        .context("build config")?;
+        .with_context(|| format!("build config from {}", config_dir.display()))?;
 
-    let s = settings
+    let mut table = settings.collect().context("collect config")?;
+    read_secret_files(&mut table, "")?;
+
+    let mut s = Value::new(None, ValueKind::Table(table))
         .try_deserialize::<Settings>()
         .context("deserialize into settings")?;
+    s.environment = environment;
+
+    // the usual way to hand a db to an app, so it beats everything else
+    if let Ok(url) = std::env::var(DATABASE_URL_ENV) {
+        s.db.apply_database_url(&url)
+            .with_context(|| format!("read {DATABASE_URL_ENV}"))?;
+    }
 
     Ok(s)
 }
+
+/// Replaces every `{key}_file` with `{key}` set to the contents of the file
+/// it names, so secrets can be mounted as files instead of sitting in yaml or
+/// the environment. The file wins when both are set. One trailing newline is
+/// dropped since most tools that write secret files add one.
+fn read_secret_files(table: &mut Map<String, Value>, path: &str) -> Result<()> {
+    let file_keys: Vec<(String, String)> = table
+        .keys()
+        .filter_map(|file_key| {
+            let key = file_key.strip_suffix(SECRET_FILE_SUFFIX)?;
+            Some((file_key.clone(), key.to_string()))
+        })
+        .collect();
+
+    for (file_key, key) in file_keys {
+        let file = table
+            .remove(&file_key)
+            .expect("key was just listed")
+            .into_string()
+            .with_context(|| format!("{path}{file_key} must be a path"))?;
+        let contents = std::fs::read_to_string(&file)
+            .with_context(|| format!("read {path}{file_key} from {file}"))?;
+        let contents = contents
+            .strip_suffix('\n')
+            .map(|c| c.strip_suffix('\r').unwrap_or(c))
+            .unwrap_or(&contents);
+
+        table.insert(key, Value::new(None, contents));
+    }
+
+    for (key, value) in table.iter_mut() {
+        if let ValueKind::Table(nested) = &mut value.kind {
+            read_secret_files(nested, &format!("{path}{key}."))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The first of `APP_CONFIG_DIR`, `configuration/` next to the executable, or
+/// `configuration/` under the CWD. The env var is taken as is, the others only
+/// if they exist.
+pub fn resolve_config_dir() -> Result<PathBuf> {
+    if let Some(dir) = std::env::var_os(CONFIG_DIR_ENV) {
+        return Ok(PathBuf::from(dir));
+    }
+
+    let beside_exe = std::env::current_exe()
+        .context("find current executable")?
+        .parent()
+        .map(|dir| dir.join(CONFIG_DIR_NAME));
+    if let Some(dir) = beside_exe.filter(|dir| dir.is_dir()) {
+        return Ok(dir);
+    }
+
+    let cwd = std::env::current_dir().context("find current directory")?;
+    Ok(cwd.join(CONFIG_DIR_NAME))
+}
+
+/// A setting that doesn't make sense, `key` is its dotted path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SettingsError {
+    pub key: String,
+    pub reason: String,
+}
+
+impl SettingsError {
+    fn new(key: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self {
+            key: key.into(),
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.key, self.reason)
+    }
+}
+
+impl Settings {
+    /// Checks what deserializing can't, returning every problem at once
+    pub fn validate(&self) -> Vec<SettingsError> {
+        let mut errors = Vec::new();
+
+        // a random port is only useful when something can go find out which
+        if self.environment != TEST_ENVIRONMENT {
+            if self.application.port == 0 {
+                errors.push(SettingsError::new(
+                    "application.port",
+                    "must not be 0 outside the test environment",
+                ));
+            }
+            if self.metrics.enabled && self.metrics.port == 0 {
+                errors.push(SettingsError::new(
+                    "metrics.port",
+                    "must not be 0 outside the test environment",
+                ));
+            }
+        }
+
+        if self.metrics.enabled
+            && self.metrics.port != 0
+            && self.metrics.port == self.application.port
+        {
+            errors.push(SettingsError::new(
+                "metrics.port",
+                format!(
+                    "must differ from application.port ({})",
+                    self.application.port
+                ),
+            ));
+        }
+
+        if self.application.host.is_empty() {
+            errors.push(SettingsError::new("application.host", "must not be empty"));
+        }
+
+        if self.application.tls.enabled {
+            for (key, path) in [
+                ("application.tls.cert_path", &self.application.tls.cert_path),
+                ("application.tls.key_path", &self.application.tls.key_path),
+            ] {
+                if !path.is_file() {
+                    errors.push(SettingsError::new(
+                        key,
+                        format!("{} is not a file", path.display()),
+                    ));
+                }
+            }
+
+            if self.application.tls.reload_interval_ms == 0 {
+                errors.push(SettingsError::new(
+                    "application.tls.reload_interval_ms",
+                    "must be at least 1",
+                ));
+            }
+        }
+
+        if self.application.http.http2_max_concurrent_streams == 0 {
+            errors.push(SettingsError::new(
+                "application.http.http2_max_concurrent_streams",
+                "must be at least 1",
+            ));
+        }
+
+        if self.application.http.http2_keep_alive_interval_ms != 0
+            && self.application.http.http2_keep_alive_timeout_ms == 0
+        {
+            errors.push(SettingsError::new(
+                "application.http.http2_keep_alive_timeout_ms",
+                "must be at least 1 when http2_keep_alive_interval_ms is set",
+            ));
+        }
+
+        if self.auth.jwt.enabled {
+            let jwt = &self.auth.jwt;
+            if jwt.issuer.is_empty() {
+                errors.push(SettingsError::new("auth.jwt.issuer", "must not be empty"));
+            }
+            if jwt.audience.is_empty() {
+                errors.push(SettingsError::new("auth.jwt.audience", "must not be empty"));
+            }
+
+            match (&jwt.jwks_url, &jwt.jwks_path) {
+                (Some(url), None) => {
+                    if !Url::parse(url).is_ok_and(|url| matches!(url.scheme(), "http" | "https")) {
+                        errors.push(SettingsError::new(
+                            "auth.jwt.jwks_url",
+                            "must be an http(s) url",
+                        ));
+                    }
+                }
+                (None, Some(path)) => {
+                    if !path.is_file() {
+                        errors.push(SettingsError::new(
+                            "auth.jwt.jwks_path",
+                            format!("{} is not a file", path.display()),
+                        ));
+                    }
+                }
+                _ => errors.push(SettingsError::new(
+                    "auth.jwt.jwks_url",
+                    "exactly one of auth.jwt.jwks_url and auth.jwt.jwks_path must be set",
+                )),
+            }
+
+            if jwt.jwks_refresh_interval_ms == 0 {
+                errors.push(SettingsError::new(
+                    "auth.jwt.jwks_refresh_interval_ms",
+                    "must be at least 1",
+                ));
+            }
+        }
+
+        if self.rate_limit.enabled {
+            for (key, limit) in [
+                ("rate_limit.cats", &self.rate_limit.cats),
+                ("rate_limit.latency", &self.rate_limit.latency),
+            ] {
+                if limit.burst == 0 {
+                    errors.push(SettingsError::new(
+                        format!("{key}.burst"),
+                        "must be at least 1",
+                    ));
+                }
+                if !(limit.per_second > 0.0 && limit.per_second.is_finite()) {
+                    errors.push(SettingsError::new(
+                        format!("{key}.per_second"),
+                        "must be greater than 0",
+                    ));
+                }
+            }
+
+            for proxy in &self.rate_limit.trusted_proxies {
+                if proxy.parse::<IpNet>().is_err() && proxy.parse::<IpAddr>().is_err() {
+                    errors.push(SettingsError::new(
+                        "rate_limit.trusted_proxies",
+                        format!("{proxy} is not an ip or cidr"),
+                    ));
+                }
+            }
+        }
+
+        if self.application.max_page_size == 0 {
+            errors.push(SettingsError::new(
+                "application.max_page_size",
+                "must be at least 1",
+            ));
+        }
+
+        if self.db.max_connections == 0 {
+            errors.push(SettingsError::new(
+                "db.max_connections",
+                "must be at least 1",
+            ));
+        }
+
+        if self.db.min_connections > self.db.max_connections {
+            errors.push(SettingsError::new(
+                "db.min_connections",
+                format!(
+                    "must not exceed db.max_connections ({})",
+                    self.db.max_connections
+                ),
+            ));
+        }
+
+        if self.db.connect_backoff_initial_ms > self.db.connect_backoff_max_ms {
+            errors.push(SettingsError::new(
+                "db.connect_backoff_initial_ms",
+                format!(
+                    "must not exceed db.connect_backoff_max_ms ({})",
+                    self.db.connect_backoff_max_ms
+                ),
+            ));
+        }
+
+        if self.environment == "production" && !self.db.ssl_mode().is_required() {
+            errors.push(SettingsError::new(
+                "db.ssl_mode",
+                "must be require, verify-ca or verify-full in production",
+            ));
+        }
+
+        for (key, path) in [
+            ("db.ssl_root_cert", &self.db.ssl_root_cert),
+            ("db.ssl_client_cert", &self.db.ssl_client_cert),
+            ("db.ssl_client_key", &self.db.ssl_client_key),
+        ] {
+            if let Some(path) = path.as_ref().filter(|path| !path.is_file()) {
+                errors.push(SettingsError::new(
+                    key,
+                    format!("{} is not a file", path.display()),
+                ));
+            }
+        }
+
+        // one is no use without the other
+        match (&self.db.ssl_client_cert, &self.db.ssl_client_key) {
+            (Some(_), None) => errors.push(SettingsError::new(
+                "db.ssl_client_key",
+                "must be set along with db.ssl_client_cert",
+            )),
+            (None, Some(_)) => errors.push(SettingsError::new(
+                "db.ssl_client_cert",
+                "must be set along with db.ssl_client_key",
+            )),
+            _ => {}
+        }
+
+        if !(0.0..=1.0).contains(&self.otlp.sampling_ratio) {
+            errors.push(SettingsError::new(
+                "otlp.sampling_ratio",
+                "must be between 0 and 1",
+            ));
+        }
+
+        errors
+    }
+}
+
+/// A list that can also be given as one comma separated string, which is all
+/// an environment variable can hold
+fn deserialize_list<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum List {
+        Items(Vec<String>),
+        Joined(String),
+    }
+
+    Ok(match List::deserialize(deserializer)? {
+        List::Items(items) => items,
+        List::Joined(joined) => joined
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(str::to_string)
+            .collect(),
+    })
+}
+
+/// Secrets only ever serialize as a placeholder
+fn redacted<S: Serializer>(
+    _: &SecretString,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str("[REDACTED]")
+}
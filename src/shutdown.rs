@@ -0,0 +1,59 @@
+use crate::error::Result;
+use anyhow::Context;
+use std::sync::Arc;
+use tokio::signal::unix::{SignalKind, signal};
+use tokio::sync::watch;
+use tracing::info;
+
+/// Shared flag for "the app is going away". Flipped by SIGINT / SIGTERM, or by
+/// hand through [`Shutdown::trigger`], and watched by the servers and the
+/// readiness check.
+#[derive(Clone, Debug)]
+pub struct Shutdown {
+    tx: Arc<watch::Sender<bool>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self {
+            tx: Arc::new(watch::Sender::new(false)),
+        }
+    }
+}
+
+impl Shutdown {
+    pub fn trigger(&self) {
+        self.tx.send_replace(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.tx.borrow()
+    }
+
+    /// Resolves once shutdown has been triggered
+    pub async fn triggered(&self) {
+        let mut rx = self.tx.subscribe();
+        // the sender lives in self, so this can't see it dropped
+        let _ = rx.wait_for(|triggered| *triggered).await;
+    }
+
+    /// Triggers shutdown on the first SIGINT or SIGTERM. The handlers are
+    /// registered before returning so registration errors surface here rather
+    /// than in a background task.
+    pub fn listen_for_signals(&self) -> Result<()> {
+        let mut sigint = signal(SignalKind::interrupt()).context("listen for SIGINT")?;
+        let mut sigterm = signal(SignalKind::terminate()).context("listen for SIGTERM")?;
+
+        let shutdown = self.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = sigint.recv() => info!("received SIGINT"),
+                _ = sigterm.recv() => info!("received SIGTERM"),
+                _ = shutdown.triggered() => return,
+            }
+            shutdown.trigger();
+        });
+
+        Ok(())
+    }
+}
@@ -1,22 +1,190 @@
 use crate::error::Result;
+use crate::request_id;
+use crate::settings::{LogFormat, LoggingSettings, OtlpProtocol, OtlpSettings};
 use anyhow::Context;
-use tracing::Subscriber;
+use axum::extract::{MatchedPath, Request};
+use axum::response::Response;
+use opentelemetry::global;
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_http::HeaderExtractor;
+use opentelemetry_otlp::{Protocol, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{Sampler, SdkTracerProvider};
+use std::time::Duration;
+use tracing::field::Empty;
 use tracing::subscriber::set_global_default;
+use tracing::{Span, Subscriber, info, info_span, warn};
+use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
 use tracing_log::LogTracer;
-use tracing_subscriber::{EnvFilter, Registry, layer::SubscriberExt};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::{EnvFilter, Layer, Registry, layer::SubscriberExt};
 
-pub fn get_subscriber() -> Result<impl Subscriber + Send + Sync> {
+/// Keeps the span exporter alive, flushing whatever is still buffered when
+/// dropped. Hold onto it until the app exits.
+#[must_use]
+pub struct TelemetryGuard {
+    tracer_provider: Option<SdkTracerProvider>,
+}
+
+impl TelemetryGuard {
+    /// Exports every finished span right away rather than waiting on the batch
+    pub fn force_flush(&self) -> Result<()> {
+        if let Some(provider) = &self.tracer_provider {
+            provider.force_flush().context("flush spans")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        if let Some(provider) = self.tracer_provider.take()
+            && let Err(e) = provider.shutdown()
+        {
+            warn!("failed to shut down tracer provider: {e:?}");
+        }
+    }
+}
+
+pub fn get_subscriber(
+    logging: &LoggingSettings,
+    otlp: &OtlpSettings,
+) -> Result<(impl Subscriber + Send + Sync, TelemetryGuard)> {
+    get_subscriber_with_writer(logging, otlp, std::io::stdout)
+}
+
+/// Same as [`get_subscriber`], but logs go to `make_writer` instead of stdout
+pub fn get_subscriber_with_writer<W>(
+    logging: &LoggingSettings,
+    otlp: &OtlpSettings,
+    make_writer: W,
+) -> Result<(impl Subscriber + Send + Sync, TelemetryGuard)>
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
     let env_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
 
+    let fmt_layer = match logging.format {
+        LogFormat::Pretty => tracing_subscriber::fmt::Layer::new()
+            .pretty()
+            .with_writer(make_writer)
+            .boxed(),
+        LogFormat::Compact => tracing_subscriber::fmt::Layer::new()
+            .compact()
+            .with_writer(make_writer)
+            .boxed(),
+        // the storage layer is what gets span fields onto each line
+        LogFormat::Json => JsonStorageLayer
+            .and_then(BunyanFormattingLayer::new(
+                env!("CARGO_PKG_NAME").to_string(),
+                make_writer,
+            ))
+            .boxed(),
+    };
+
+    let tracer_provider = if otlp.enabled {
+        Some(get_tracer_provider(otlp)?)
+    } else {
+        None
+    };
+
+    let otel_layer = tracer_provider.as_ref().map(|provider| {
+        tracing_opentelemetry::layer().with_tracer(provider.tracer(otlp.service_name.clone()))
+    });
+
     let registry = Registry::default()
         .with(env_filter)
-        .with(tracing_subscriber::fmt::Layer::new());
+        .with(fmt_layer)
+        .with(otel_layer);
+
+    Ok((registry, TelemetryGuard { tracer_provider }))
+}
+
+fn get_tracer_provider(otlp: &OtlpSettings) -> Result<SdkTracerProvider> {
+    let exporter = match otlp.protocol {
+        OtlpProtocol::Grpc => SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(&otlp.endpoint)
+            .build(),
+        OtlpProtocol::HttpProtobuf | OtlpProtocol::HttpJson => {
+            let protocol = if otlp.protocol == OtlpProtocol::HttpJson {
+                Protocol::HttpJson
+            } else {
+                Protocol::HttpBinary
+            };
+
+            SpanExporter::builder()
+                .with_http()
+                .with_protocol(protocol)
+                .with_endpoint(format!("{}/v1/traces", otlp.endpoint.trim_end_matches('/')))
+                .build()
+        }
+    }
+    .context("build otlp span exporter")?;
+
+    // respect the caller's decision when there is one
+    let sampler = Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(otlp.sampling_ratio)));
 
-    Ok(registry)
+    let resource = Resource::builder()
+        .with_service_name(otlp.service_name.clone())
+        .build();
+
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_sampler(sampler)
+        .with_resource(resource)
+        .build())
 }
 
+/// Installs `subscriber` for the whole process. Only the first call can win,
+/// later ones return an error rather than panicking so tests can call this
+/// freely.
 pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) -> Result<()> {
+    set_global_default(subscriber).context("set global subscriber")?;
     LogTracer::init().context("init log tracer")?;
-    set_global_default(subscriber).expect("Failed to set subscriber");
+    global::set_text_map_propagator(TraceContextPropagator::new());
     Ok(())
 }
+
+/// Span for each request handled by the `TraceLayer`, continuing the trace
+/// from an incoming W3C `traceparent` header when there is one. `status` and
+/// `latency_ms` get filled in by [`on_response`].
+pub fn make_request_span(req: &Request) -> Span {
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str())
+        .unwrap_or("unmatched");
+
+    let span = info_span!(
+        "request",
+        method = %req.method(),
+        uri = %req.uri(),
+        version = ?req.version(),
+        route,
+        request_id = request_id::current().unwrap_or_default(),
+        status = Empty,
+        latency_ms = Empty,
+        otel.name = format!("{} {route}", req.method()),
+        otel.kind = "server",
+    );
+
+    let parent = global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(req.headers()))
+    });
+    // only fails if there's no otel layer installed, which is fine
+    let _ = span.set_parent(parent);
+
+    span
+}
+
+/// The access log line for every request
+pub fn on_response(response: &Response, latency: Duration, span: &Span) {
+    span.record("status", response.status().as_u16());
+    span.record("latency_ms", latency.as_millis() as u64);
+
+    info!("finished processing request");
+}
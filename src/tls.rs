@@ -0,0 +1,218 @@
+use crate::settings::TlsSettings;
+use anyhow::Context;
+use axum::serve::Listener;
+use rustls::ServerConfig;
+use rustls::crypto::{CryptoProvider, ring};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock, Weak};
+use std::time::{Duration, SystemTime};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::server::TlsStream;
+use tracing::{debug, info, warn};
+
+/// How long a client gets to finish the handshake before we hang up
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Handshakes finished but not yet picked up by the server
+const ACCEPT_BACKLOG: usize = 64;
+
+/// Everything needed to terminate TLS, loaded once at startup
+pub struct Tls {
+    config: Arc<ServerConfig>,
+    resolver: Arc<CertResolver>,
+    settings: TlsSettings,
+}
+
+impl Tls {
+    /// Loads the cert and key, failing if they're unreadable or don't match
+    pub fn load(settings: &TlsSettings) -> anyhow::Result<Self> {
+        let provider = Arc::new(ring::default_provider());
+        let files = CertFiles::new(settings);
+        let resolver = Arc::new(CertResolver {
+            current: RwLock::new(Arc::new(files.load(&provider)?)),
+            files,
+            provider: provider.clone(),
+        });
+
+        let mut config = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .context("pick tls versions")?
+            .with_no_client_auth()
+            .with_cert_resolver(resolver.clone());
+        // prefer http/2, clients that can't do it fall back to http/1.1
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+        Ok(Self {
+            config: Arc::new(config),
+            resolver,
+            settings: settings.clone(),
+        })
+    }
+
+    /// Starts handshaking connections from `listener` and watching the cert
+    /// files for changes, both stop once the returned listener is dropped
+    pub fn listen(self, listener: TcpListener) -> std::io::Result<TlsListener> {
+        let local_addr = listener.local_addr()?;
+        let (tx, rx) = mpsc::channel(ACCEPT_BACKLOG);
+
+        tokio::spawn(accept_loop(listener, TlsAcceptor::from(self.config), tx));
+        tokio::spawn(reload_loop(
+            Arc::downgrade(&self.resolver),
+            Duration::from_millis(self.settings.reload_interval_ms),
+        ));
+
+        Ok(TlsListener {
+            incoming: rx,
+            local_addr,
+        })
+    }
+}
+
+/// Hands out connections that already finished their handshake, so a slow
+/// client can't hold up everyone else's accept
+pub struct TlsListener {
+    incoming: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+    local_addr: SocketAddr,
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.incoming.recv().await {
+            Some(conn) => conn,
+            // the accept loop only stops once we're gone, so this can't happen
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> std::io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+async fn accept_loop(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    tx: mpsc::Sender<(TlsStream<TcpStream>, SocketAddr)>,
+) {
+    loop {
+        let (stream, addr) = tokio::select! {
+            _ = tx.closed() => return,
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    // usually out of file descriptors, give it a moment
+                    warn!("accepting connection failed: {e}");
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    continue;
+                }
+            },
+        };
+
+        let acceptor = acceptor.clone();
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => {
+                    let _ = tx.send((stream, addr)).await;
+                }
+                Ok(Err(e)) => debug!("tls handshake with {addr} failed: {e}"),
+                Err(_) => debug!("tls handshake with {addr} timed out"),
+            }
+        });
+    }
+}
+
+/// Swaps in the new cert whenever the files change. A cert that fails to load
+/// is logged and the old one kept, so a half written file doesn't take us down.
+async fn reload_loop(resolver: Weak<CertResolver>, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+        let Some(resolver) = resolver.upgrade() else {
+            return;
+        };
+
+        if !resolver.files.changed() {
+            continue;
+        }
+
+        match resolver.files.load(&resolver.provider) {
+            Ok(key) => {
+                *resolver.current.write().expect("cert lock poisoned") = Arc::new(key);
+                info!("reloaded tls certificate");
+            }
+            Err(e) => warn!("reloading tls certificate failed, keeping the old one: {e:?}"),
+        }
+    }
+}
+
+/// Always answers with whichever cert was loaded last
+#[derive(Debug)]
+struct CertResolver {
+    current: RwLock<Arc<CertifiedKey>>,
+    files: CertFiles,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().expect("cert lock poisoned").clone())
+    }
+}
+
+#[derive(Debug)]
+struct CertFiles {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    /// modification times as of the last load
+    seen: RwLock<Option<[SystemTime; 2]>>,
+}
+
+impl CertFiles {
+    fn new(settings: &TlsSettings) -> Self {
+        Self {
+            cert_path: settings.cert_path.clone(),
+            key_path: settings.key_path.clone(),
+            seen: RwLock::new(None),
+        }
+    }
+
+    fn modified(&self) -> Option<[SystemTime; 2]> {
+        let modified = |path: &PathBuf| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        Some([modified(&self.cert_path)?, modified(&self.key_path)?])
+    }
+
+    fn changed(&self) -> bool {
+        self.modified() != *self.seen.read().expect("cert files lock poisoned")
+    }
+
+    fn load(&self, provider: &CryptoProvider) -> anyhow::Result<CertifiedKey> {
+        // noted up front so a broken cert is only retried once it changes again
+        *self.seen.write().expect("cert files lock poisoned") = self.modified();
+
+        let certs = CertificateDer::pem_file_iter(&self.cert_path)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .with_context(|| format!("read certificates from {}", self.cert_path.display()))?;
+        if certs.is_empty() {
+            anyhow::bail!("no certificates in {}", self.cert_path.display());
+        }
+        let key = PrivateKeyDer::from_pem_file(&self.key_path)
+            .with_context(|| format!("read private key from {}", self.key_path.display()))?;
+        let key = CertifiedKey::from_der(certs, key, provider)
+            .context("pair the certificate with its key")?;
+
+        Ok(key)
+    }
+}
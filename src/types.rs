@@ -1 +1,2 @@
 pub use crate::routes::v1;
+pub use crate::routes::v2;
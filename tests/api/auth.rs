@@ -0,0 +1,259 @@
+use crate::utils::{
+    TestApp, binary, create_two_cats, fresh_db_settings, spawn_app, spawn_app_with,
+};
+use anyhow::Context;
+use anyhow::Result;
+use gha_demo::api_keys::ApiKey;
+use gha_demo::auth::Scope;
+use gha_demo::types::v1::types::{Cat, EyeColor};
+use reqwest::StatusCode;
+use reqwest::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use serde_json::Value;
+use std::process::Stdio;
+use uuid::Uuid;
+
+fn new_cat() -> Cat {
+    Cat {
+        name: "Tom".to_string(),
+        cool_cat_club_id: Uuid::new_v4(),
+        age: 3,
+        eye_color: EyeColor::Blue,
+    }
+}
+
+/// POSTs a cat with whatever `Authorization` header is given, if any
+async fn create_cat(app: &TestApp, authorization: Option<&str>) -> Result<reqwest::Response> {
+    let mut request = reqwest::Client::new()
+        .post(format!("{}/v1/cats", app.address))
+        .json(&new_cat());
+    if let Some(authorization) = authorization {
+        request = request.header(AUTHORIZATION, authorization);
+    }
+    request.send().await.context("send request")
+}
+
+async fn assert_problem(resp: reqwest::Response, status: StatusCode) -> Result<Value> {
+    assert_eq!(resp.status(), status);
+    if status == StatusCode::UNAUTHORIZED {
+        assert_eq!(
+            resp.headers()
+                .get(WWW_AUTHENTICATE)
+                .context("www-authenticate header")?,
+            "Bearer"
+        );
+    }
+    let problem: Value = resp.json().await?;
+    assert_eq!(problem["status"], status.as_u16());
+    Ok(problem)
+}
+
+#[tokio::test]
+pub async fn test_writes_need_a_key() -> Result<()> {
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    let resp = create_cat(&app, None).await?;
+    let problem = assert_problem(resp, StatusCode::UNAUTHORIZED).await?;
+    assert_eq!(problem["detail"], "a bearer token is required");
+
+    // not a bearer token
+    let resp = create_cat(&app, Some("Basic dXNlcjpwYXNz")).await?;
+    assert_problem(resp, StatusCode::UNAUTHORIZED).await?;
+
+    // a key we never minted
+    let resp = create_cat(&app, Some("Bearer ccc_not-a-real-key")).await?;
+    let problem = assert_problem(resp, StatusCode::UNAUTHORIZED).await?;
+    assert_eq!(problem["detail"], "the api key is invalid or revoked");
+
+    // nothing was written
+    let count: i64 = sqlx::query_scalar("SELECT count(*) FROM cats")
+        .fetch_one(&app.db_pool)
+        .await?;
+    assert_eq!(count, 0);
+
+    // the scheme is case insensitive
+    let resp = create_cat(&app, Some(&format!("bearer {}", app.api_key))).await?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_every_write_route_needs_a_key() -> Result<()> {
+    let app = spawn_app().await.context("spawn testing app")?;
+    let [cat, _] = create_two_cats(&app.db_pool).await?;
+    let endpoint = format!("{}/v1/cats/{}", app.address, cat.cool_cat_club_id);
+
+    let client = reqwest::Client::new();
+    for request in [
+        client.put(&endpoint).json(&cat),
+        client.patch(&endpoint).json(&serde_json::json!({"age": 5})),
+        client.delete(&endpoint),
+    ] {
+        let resp = request.send().await?;
+        assert_problem(resp, StatusCode::UNAUTHORIZED).await?;
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_key_without_write_scope_is_forbidden() -> Result<()> {
+    let app = spawn_app().await.context("spawn testing app")?;
+    let (_, key) = ApiKey::mint(&app.db_pool, "read only", &[Scope::CatsRead]).await?;
+
+    let resp = create_cat(&app, Some(&format!("Bearer {key}"))).await?;
+    let problem = assert_problem(resp, StatusCode::FORBIDDEN).await?;
+    assert_eq!(problem["detail"], "the api key lacks the cats:write scope");
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_revoked_key_is_rejected() -> Result<()> {
+    let app = spawn_app().await.context("spawn testing app")?;
+    let (api_key, key) = ApiKey::mint(&app.db_pool, "short lived", &Scope::ALL).await?;
+    let authorization = format!("Bearer {key}");
+
+    let resp = create_cat(&app, Some(&authorization)).await?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    assert!(ApiKey::revoke(&app.db_pool, api_key.id).await?);
+    let resp = create_cat(&app, Some(&authorization)).await?;
+    assert_problem(resp, StatusCode::UNAUTHORIZED).await?;
+
+    // revoking twice finds nothing to revoke
+    assert!(!ApiKey::revoke(&app.db_pool, api_key.id).await?);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_public_reads() -> Result<()> {
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    // no key needed
+    let client = reqwest::Client::new();
+    for path in ["/v1/cats", "/v2/cats"] {
+        let resp = client.get(format!("{}{path}", app.address)).send().await?;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    // but one that's sent must be valid
+    let resp = client
+        .get(format!("{}/v1/cats", app.address))
+        .bearer_auth("ccc_not-a-real-key")
+        .send()
+        .await?;
+    assert_problem(resp, StatusCode::UNAUTHORIZED).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_private_reads() -> Result<()> {
+    let app = spawn_app_with(|c| c.auth.public_reads = false)
+        .await
+        .context("spawn testing app")?;
+    let [cat, _] = create_two_cats(&app.db_pool).await?;
+
+    let client = reqwest::Client::new();
+    for path in [
+        "/v1/cats".to_string(),
+        format!("/v1/cats/{}", cat.cool_cat_club_id),
+        "/v2/cats".to_string(),
+    ] {
+        let endpoint = format!("{}{path}", app.address);
+        let resp = client.get(&endpoint).send().await?;
+        assert_problem(resp, StatusCode::UNAUTHORIZED).await?;
+
+        let resp = app.api_client.get(&endpoint).send().await?;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    // a write only key can't read
+    let (_, key) = ApiKey::mint(&app.db_pool, "write only", &[Scope::CatsWrite]).await?;
+    let resp = client
+        .get(format!("{}/v1/cats", app.address))
+        .bearer_auth(key)
+        .send()
+        .await?;
+    assert_problem(resp, StatusCode::FORBIDDEN).await?;
+
+    Ok(())
+}
+
+/// Runs `gha_demo keys <args>` against `database`, returning what it printed
+fn keys(database: &str, args: &[&str]) -> Result<(i32, String)> {
+    let output = binary(&[("APP_DB__DATABASE", database)])
+        .arg("keys")
+        .args(args)
+        .stdout(Stdio::piped())
+        .output()?;
+
+    Ok((
+        output.status.code().context("exited by signal")?,
+        String::from_utf8(output.stdout)?,
+    ))
+}
+
+#[tokio::test]
+pub async fn test_keys_cli() -> Result<()> {
+    let db = fresh_db_settings(true).await?;
+    let pool = sqlx::PgPool::connect_with(db.get_db_settings()).await?;
+
+    // the key is all that's printed, logs go to stderr
+    let (code, output) = keys(&db.database, &["mint", "ci", "--scope", "cats:read"])?;
+    assert_eq!(code, 0);
+    let key = output.strip_suffix('\n').context("minted key")?;
+    assert!(key.starts_with("ccc_"), "{output}");
+    assert!(!key.contains('\n'), "{output}");
+    let api_key = ApiKey::find_active(&pool, key)
+        .await?
+        .context("key should be stored")?;
+    assert_eq!(api_key.name, "ci");
+    assert_eq!(api_key.scopes, [Scope::CatsRead]);
+
+    // only a hash of it is kept
+    let stored: i64 = sqlx::query_scalar(
+        "SELECT count(*) FROM api_keys WHERE hash = convert_to($1, 'UTF8') OR prefix = $1",
+    )
+    .bind(key)
+    .fetch_one(&pool)
+    .await?;
+    assert_eq!(stored, 0);
+
+    // every scope by default
+    let (code, _) = keys(&db.database, &["mint", "deploys"])?;
+    assert_eq!(code, 0);
+
+    let (code, output) = keys(&db.database, &["list"])?;
+    assert_eq!(code, 0);
+    assert!(output.starts_with("ID "), "{output}");
+    assert_eq!(output.lines().count(), 3, "{output}");
+    let line = output
+        .lines()
+        .find(|line| line.ends_with("deploys"))
+        .context("listed key")?;
+    assert!(line.contains("active"), "{output}");
+    assert!(line.contains("cats:read,cats:write"), "{output}");
+    assert!(!output.contains(key), "{output}");
+
+    let (code, output) = keys(&db.database, &["revoke", &api_key.id.to_string()])?;
+    assert_eq!(code, 0);
+    let line = output
+        .lines()
+        .find(|line| line.starts_with(&api_key.id.to_string()))
+        .context("listed key")?;
+    assert!(line.contains("revoked"), "{output}");
+    assert!(ApiKey::find_active(&pool, key).await?.is_none());
+
+    // again, there's nothing left to revoke
+    let (code, _) = keys(&db.database, &["revoke", &api_key.id.to_string()])?;
+    assert_eq!(code, 1);
+
+    // scopes are checked up front
+    let (code, _) = keys(&db.database, &["mint", "oops", "--scope", "dogs:write"])?;
+    assert_eq!(code, 2);
+
+    Ok(())
+}
@@ -1,3 +1,4 @@
+use crate::utils::create_n_cats;
 use crate::utils::create_two_cats;
 use crate::utils::spawn_app;
 use anyhow::Context;
@@ -6,6 +7,7 @@ use gha_demo::types::v1::types::Cat;
 use gha_demo::types::v1::types::EyeColor;
 use reqwest::StatusCode;
 use serde::Serialize;
+use serde_json::Value;
 use uuid::Uuid;
 
 #[tokio::test]
@@ -64,6 +66,97 @@ pub async fn test_get_all_cats() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+pub async fn test_get_all_cats_filtered_and_sorted() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    let created = create_n_cats(&app.db_pool, 10).await?;
+
+    // all brown eyed cats older than 3, oldest first
+    let endpoint = format!(
+        "{}/v1/cats?eye_color=Brown&min_age=4&sort=-age,name",
+        app.address
+    );
+    let resp = app
+        .api_client
+        .get(endpoint)
+        .send()
+        .await
+        .context("send request")?;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    let cats: Vec<Cat> = resp.json().await?;
+
+    let mut expected: Vec<Cat> = created
+        .into_iter()
+        .filter(|c| c.eye_color == EyeColor::Brown && c.age >= 4)
+        .collect();
+    expected.sort_by(|a, b| b.age.cmp(&a.age).then(a.name.cmp(&b.name)));
+    assert!(!expected.is_empty());
+    assert_eq!(expected, cats);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_get_all_cats_name_prefix() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    let [cat1, _] = create_two_cats(&app.db_pool).await?;
+
+    // wildcards in the prefix should be taken literally
+    let cases = vec![("Whis", vec![cat1]), ("%", vec![]), ("_hiskers", vec![])];
+
+    for (prefix, expected) in cases {
+        let endpoint = format!("{}/v1/cats", app.address);
+        let resp = app
+            .api_client
+            .get(endpoint)
+            .query(&[("name_prefix", prefix)])
+            .send()
+            .await
+            .context("send request")?;
+
+        assert_eq!(resp.status(), StatusCode::OK, "{prefix}");
+        let cats: Vec<Cat> = resp.json().await?;
+        assert_eq!(expected, cats, "{prefix}");
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_get_all_cats_invalid_query() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    let cases = vec![
+        ("color=Blue", "Unknown Field"),
+        ("eye_color=Green", "Unknown Eye Color"),
+        ("min_age=old", "Malformed Age"),
+        ("sort=weight", "Unknown Sort Key"),
+        ("sort=age,,name", "Empty Sort Key"),
+        ("sort=age,-age", "Duplicate Sort Key"),
+        ("sort=", "Empty Sort"),
+    ];
+
+    for (query, msg) in cases {
+        let endpoint = format!("{}/v1/cats?{query}", app.address);
+        let resp = app
+            .api_client
+            .get(endpoint)
+            .send()
+            .await
+            .context("send request")?;
+
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "{msg}");
+    }
+
+    Ok(())
+}
+
 #[tokio::test]
 pub async fn test_get_cat() -> Result<()> {
     // spawn our app
@@ -160,6 +253,37 @@ pub async fn test_create_valid_cat() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+pub async fn test_create_duplicate_cat() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    let [cat1, _] = create_two_cats(&app.db_pool).await?;
+
+    // same id as an existing cat
+    let duplicate = Cat {
+        name: "Impostor".to_string(),
+        ..cat1
+    };
+
+    let post_endpoint = format!("{}/v1/cats", app.address);
+    let resp = app
+        .api_client
+        .post(post_endpoint)
+        .json(&duplicate)
+        .send()
+        .await
+        .context("send request")?;
+
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+    // body should tell us which id collided
+    let body = resp.text().await?;
+    assert!(body.contains(&duplicate.cool_cat_club_id.to_string()));
+
+    Ok(())
+}
+
 // normally would be good to put this in its own file
 
 #[derive(Serialize, Debug)]
@@ -213,11 +337,12 @@ pub async fn test_create_invalid_cat() -> Result<()> {
     let post_endpoint = format!("{}/v1/cats", app.address);
 
     // cat
-    let mut cases: Vec<(TestCat, &str)> = Vec::new();
-    cases.push((TestCat::default().with_name(None), "Missing Name"));
-    cases.push((TestCat::default().with_cool_cat_club_id(None), "Missing ID"));
-    cases.push((TestCat::default().with_age(None), "Missing Age"));
-    cases.push((TestCat::default().with_eye_color(None), "Missing Eye Color"));
+    let cases: Vec<(TestCat, &str)> = vec![
+        (TestCat::default().with_name(None), "Missing Name"),
+        (TestCat::default().with_cool_cat_club_id(None), "Missing ID"),
+        (TestCat::default().with_age(None), "Missing Age"),
+        (TestCat::default().with_eye_color(None), "Missing Eye Color"),
+    ];
 
     for (cat, msg) in cases {
         // send the request
@@ -234,3 +359,430 @@ pub async fn test_create_invalid_cat() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+pub async fn test_replace_cat() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    let [cat1, _] = create_two_cats(&app.db_pool).await?;
+
+    // same id, everything else changed
+    let replacement = Cat {
+        name: "Sir Whiskers".to_string(),
+        cool_cat_club_id: cat1.cool_cat_club_id,
+        age: 7,
+        eye_color: EyeColor::Brown,
+    };
+
+    // send the request
+    let endpoint = format!("{}/v1/cats/{}", app.address, cat1.cool_cat_club_id);
+    let resp = app
+        .api_client
+        .put(&endpoint)
+        .json(&replacement)
+        .send()
+        .await
+        .context("send request")?;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // get the cat using the API to make sure it stuck
+    let resp = app
+        .api_client
+        .get(&endpoint)
+        .send()
+        .await
+        .context("send request")?;
+
+    let gotten_cat: Cat = resp.json().await?;
+    assert_eq!(replacement, gotten_cat);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_replace_cat_not_found() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    let cat = Cat {
+        name: "maisy".to_string(),
+        cool_cat_club_id: Uuid::new_v4(),
+        age: 3,
+        eye_color: EyeColor::Blue,
+    };
+
+    // send the request
+    let endpoint = format!("{}/v1/cats/{}", app.address, cat.cool_cat_club_id);
+    let resp = app
+        .api_client
+        .put(endpoint)
+        .json(&cat)
+        .send()
+        .await
+        .context("send request")?;
+
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_replace_cat_id_mismatch() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    let [cat1, cat2] = create_two_cats(&app.db_pool).await?;
+
+    // try to replace cat1 with a body describing cat2
+    let endpoint = format!("{}/v1/cats/{}", app.address, cat1.cool_cat_club_id);
+    let resp = app
+        .api_client
+        .put(endpoint)
+        .json(&cat2)
+        .send()
+        .await
+        .context("send request")?;
+
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_update_cat() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    let [cat1, _] = create_two_cats(&app.db_pool).await?;
+
+    // only touch the age and eye color
+    let patch = serde_json::json!({ "age": 9, "eye_color": "Brown" });
+
+    let endpoint = format!("{}/v1/cats/{}", app.address, cat1.cool_cat_club_id);
+    let resp = app
+        .api_client
+        .patch(endpoint)
+        .header("Content-Type", "application/merge-patch+json")
+        .body(patch.to_string())
+        .send()
+        .await
+        .context("send request")?;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let updated: Cat = resp.json().await?;
+    let expected = Cat {
+        age: 9,
+        eye_color: EyeColor::Brown,
+        ..cat1
+    };
+    assert_eq!(expected, updated);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_update_cat_invalid() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    let [cat1, _] = create_two_cats(&app.db_pool).await?;
+    let endpoint = format!("{}/v1/cats/{}", app.address, cat1.cool_cat_club_id);
+
+    let cases = vec![
+        (
+            serde_json::json!({ "name": null }),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Removing Name",
+        ),
+        (
+            serde_json::json!({ "age": "old" }),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Wrong Age Type",
+        ),
+        (
+            serde_json::json!({ "cool_cat_club_id": Uuid::new_v4() }),
+            StatusCode::BAD_REQUEST,
+            "Changing ID",
+        ),
+    ];
+
+    for (patch, status, msg) in cases {
+        let resp = app
+            .api_client
+            .patch(&endpoint)
+            .header("Content-Type", "application/merge-patch+json")
+            .body(patch.to_string())
+            .send()
+            .await
+            .context("send request")?;
+
+        assert_eq!(resp.status(), status, "{msg}");
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_update_cat_not_found() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    let endpoint = format!("{}/v1/cats/{}", app.address, Uuid::new_v4());
+    let resp = app
+        .api_client
+        .patch(endpoint)
+        .header("Content-Type", "application/merge-patch+json")
+        .body(serde_json::json!({ "age": 1 }).to_string())
+        .send()
+        .await
+        .context("send request")?;
+
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_delete_cat() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    let [cat1, _] = create_two_cats(&app.db_pool).await?;
+
+    let endpoint = format!("{}/v1/cats/{}", app.address, cat1.cool_cat_club_id);
+    let resp = app
+        .api_client
+        .delete(&endpoint)
+        .send()
+        .await
+        .context("send request")?;
+
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+    // cat should be gone now
+    let resp = app
+        .api_client
+        .get(&endpoint)
+        .send()
+        .await
+        .context("send request")?;
+
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_delete_cat_not_found() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    let endpoint = format!("{}/v1/cats/{}", app.address, Uuid::new_v4());
+    let resp = app
+        .api_client
+        .delete(endpoint)
+        .send()
+        .await
+        .context("send request")?;
+
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_create_cat_fails_validation() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    let post_endpoint = format!("{}/v1/cats", app.address);
+
+    // each case lists the fields we expect to be reported
+    let cases: Vec<(TestCat, Vec<&str>, &str)> = vec![
+        (
+            TestCat::default().with_name(Some("".to_string())),
+            vec!["name"],
+            "Empty Name",
+        ),
+        (
+            TestCat::default().with_name(Some("x".repeat(65))),
+            vec!["name"],
+            "Long Name",
+        ),
+        (
+            TestCat::default().with_name(Some(" KITTY".to_string())),
+            vec!["name"],
+            "Untrimmed Name",
+        ),
+        (
+            TestCat::default().with_name(Some("KITTY; DROP TABLE".to_string())),
+            vec!["name"],
+            "Bad Characters",
+        ),
+        (
+            TestCat::default().with_name(Some("Zoë".to_string())),
+            vec!["name"],
+            "Non-ASCII Letter",
+        ),
+        (
+            TestCat::default().with_name(Some("\tKITTY".to_string())),
+            vec!["name"],
+            "Leading Tab",
+        ),
+        (
+            TestCat::default().with_name(Some("KITTY\u{a0}".to_string())),
+            vec!["name"],
+            "Trailing NBSP",
+        ),
+        (
+            TestCat::default().with_age(Some(-1)),
+            vec!["age"],
+            "Negative Age",
+        ),
+        (
+            TestCat::default().with_age(Some(30000)),
+            vec!["age"],
+            "Ancient Cat",
+        ),
+        (
+            TestCat::default().with_cool_cat_club_id(Some(Uuid::nil())),
+            vec!["cool_cat_club_id"],
+            "Nil ID",
+        ),
+        (
+            TestCat::default()
+                .with_name(Some("".to_string()))
+                .with_age(Some(-1))
+                .with_cool_cat_club_id(Some(Uuid::nil())),
+            vec!["name", "age", "cool_cat_club_id"],
+            "Everything Wrong",
+        ),
+    ];
+
+    for (cat, fields, msg) in cases {
+        let resp = app
+            .api_client
+            .post(&post_endpoint)
+            .json(&cat)
+            .send()
+            .await
+            .context("send request")?;
+
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY, "{msg}");
+
+        let problem: Value = resp.json().await?;
+        let reported: Vec<&str> = problem["errors"]
+            .as_array()
+            .context("errors array")?
+            .iter()
+            .filter_map(|e| e["field"].as_str())
+            .collect();
+        assert_eq!(reported, fields, "{msg}");
+    }
+
+    // nothing should have made it into the db
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM cats")
+        .fetch_one(&app.db_pool)
+        .await?;
+    assert_eq!(count, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_update_cat_fails_validation() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    let [cat1, _] = create_two_cats(&app.db_pool).await?;
+    let endpoint = format!("{}/v1/cats/{}", app.address, cat1.cool_cat_club_id);
+
+    // through PUT
+    let invalid = Cat { age: 31, ..cat1 };
+    let resp = app
+        .api_client
+        .put(&endpoint)
+        .json(&invalid)
+        .send()
+        .await
+        .context("send request")?;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    // and through PATCH
+    let resp = app
+        .api_client
+        .patch(&endpoint)
+        .header("Content-Type", "application/merge-patch+json")
+        .body(serde_json::json!({ "name": "" }).to_string())
+        .send()
+        .await
+        .context("send request")?;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_db_rejects_invalid_cat() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    // skip the api entirely, the check constraints should still catch these
+    let cases = vec![
+        ("", 3, Uuid::new_v4(), "Empty Name"),
+        (" KITTY", 3, Uuid::new_v4(), "Untrimmed Name"),
+        ("KITTY!", 3, Uuid::new_v4(), "Bad Characters"),
+        ("Zoë", 3, Uuid::new_v4(), "Non-ASCII Letter"),
+        ("\tKITTY", 3, Uuid::new_v4(), "Leading Tab"),
+        ("KITTY\u{a0}", 3, Uuid::new_v4(), "Trailing NBSP"),
+        ("KITTY", 31, Uuid::new_v4(), "Ancient Cat"),
+        ("KITTY", 3, Uuid::nil(), "Nil ID"),
+    ];
+
+    for (name, age, id, msg) in cases {
+        let result = sqlx::query(
+            "INSERT INTO cats (name, cool_cat_club_id, age, eye_color) VALUES ($1, $2, $3, 'Blue')",
+        )
+        .bind(name)
+        .bind(id)
+        .bind(age as i16)
+        .execute(&app.db_pool)
+        .await;
+
+        let Err(sqlx::Error::Database(e)) = result else {
+            panic!("{msg}: expected a database error, got {result:?}");
+        };
+        assert_eq!(e.code().as_deref(), Some("23514"), "{msg}");
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_check_violation_is_unprocessable() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    // a rule the db has and validation doesn't know about
+    sqlx::query("ALTER TABLE cats ADD CONSTRAINT cats_no_toms CHECK (name <> 'Tom')")
+        .execute(&app.db_pool)
+        .await?;
+
+    let cat = TestCat::default().with_name(Some("Tom".to_string()));
+    let resp = app
+        .api_client
+        .post(format!("{}/v1/cats", app.address))
+        .json(&cat)
+        .send()
+        .await
+        .context("send request")?;
+
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let problem: Value = resp.json().await?;
+    assert_eq!(problem["detail"], "violates cats_no_toms");
+
+    Ok(())
+}
@@ -0,0 +1,192 @@
+use crate::utils::create_n_cats;
+use crate::utils::spawn_app;
+use anyhow::Context;
+use anyhow::Result;
+use gha_demo::types::v1::types::{Cat, EyeColor};
+use gha_demo::types::v2::types::CatPage;
+use reqwest::StatusCode;
+use reqwest::header::LINK;
+
+#[tokio::test]
+pub async fn test_get_cat_page_empty() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    // send the request
+    let endpoint = format!("{}/v2/cats", app.address);
+    let resp = app
+        .api_client
+        .get(endpoint)
+        .send()
+        .await
+        .context("send request")?;
+
+    // check status
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(resp.headers().get(LINK).is_none());
+
+    // no cats, no next page
+    let page: CatPage = resp.json().await?;
+    assert!(page.cats.is_empty());
+    assert!(page.next_cursor.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_get_cat_page_walk() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    let mut created = create_n_cats(&app.db_pool, 5).await?;
+
+    // walk the pages two at a time
+    let mut seen = Vec::new();
+    let mut endpoint = format!("{}/v2/cats?limit=2", app.address);
+    let mut pages = 0;
+    loop {
+        let resp = app
+            .api_client
+            .get(&endpoint)
+            .send()
+            .await
+            .context("send request")?;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let link = resp.headers().get(LINK).cloned();
+        let page: CatPage = resp.json().await?;
+        assert!(page.cats.len() <= 2);
+        pages += 1;
+        seen.extend(page.cats);
+
+        // link header and body should agree on the next page
+        let Some(next_cursor) = page.next_cursor else {
+            assert!(link.is_none());
+            break;
+        };
+        let link = link.context("link header on non-final page")?;
+        assert!(link.to_str()?.contains(&next_cursor));
+        assert!(link.to_str()?.ends_with(r#"rel="next""#));
+
+        endpoint = format!("{}/v2/cats?limit=2&cursor={next_cursor}", app.address);
+    }
+
+    // every cat exactly once
+    assert_eq!(pages, 3);
+    created.sort_by_key(|c| c.cool_cat_club_id);
+    assert_eq!(created, seen);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_get_cat_page_walk_filtered_and_sorted() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    let created = create_n_cats(&app.db_pool, 25).await?;
+
+    // mixed directions and lots of age ties to exercise the cursor
+    let mut seen: Vec<Cat> = Vec::new();
+    let mut endpoint = format!(
+        "{}/v2/cats?eye_color=Blue&sort=-age,name&limit=3",
+        app.address
+    );
+    loop {
+        let resp = app
+            .api_client
+            .get(&endpoint)
+            .send()
+            .await
+            .context("send request")?;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let link = resp.headers().get(LINK).cloned();
+        let page: CatPage = resp.json().await?;
+        seen.extend(page.cats);
+
+        if page.next_cursor.is_none() {
+            break;
+        }
+
+        // follow the link header, it should carry the filters along
+        let link = link.context("link header on non-final page")?;
+        let link = link.to_str()?;
+        assert!(link.contains("eye_color=Blue"));
+        assert!(link.contains("sort=-age,name"));
+        let path = link
+            .trim_start_matches('<')
+            .split('>')
+            .next()
+            .context("link target")?;
+        endpoint = format!("{}{path}", app.address);
+    }
+
+    let mut expected: Vec<Cat> = created
+        .into_iter()
+        .filter(|c| c.eye_color == EyeColor::Blue)
+        .collect();
+    expected.sort_by(|a, b| {
+        b.age
+            .cmp(&a.age)
+            .then(a.name.cmp(&b.name))
+            .then(a.cool_cat_club_id.cmp(&b.cool_cat_club_id))
+    });
+    assert_eq!(expected, seen);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_get_cat_page_clamps_limit() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    create_n_cats(&app.db_pool, 3).await?;
+
+    // ask for way more than the server allows, we should still get everything
+    // since the max page size is larger than 3
+    let endpoint = format!("{}/v2/cats?limit={}", app.address, u32::MAX);
+    let resp = app
+        .api_client
+        .get(endpoint)
+        .send()
+        .await
+        .context("send request")?;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    let page: CatPage = resp.json().await?;
+    assert_eq!(page.cats.len(), 3);
+    assert!(page.next_cursor.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_get_cat_page_invalid_query() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    let cases = vec![
+        ("limit=0", "Zero Limit"),
+        ("limit=-1", "Negative Limit"),
+        ("cursor=not-a-cursor", "Garbage Cursor"),
+        ("cursor=AAAA", "Short Cursor"),
+        ("sort=-weight", "Unknown Sort Key"),
+        ("page=2", "Unknown Field"),
+    ];
+
+    for (query, msg) in cases {
+        let endpoint = format!("{}/v2/cats?{query}", app.address);
+        let resp = app
+            .api_client
+            .get(endpoint)
+            .send()
+            .await
+            .context("send request")?;
+
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "{msg}");
+    }
+
+    Ok(())
+}
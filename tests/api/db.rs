@@ -0,0 +1,100 @@
+use crate::utils::{spawn_app_with, test_settings};
+use anyhow::Context;
+use anyhow::Result;
+use gha_demo::settings::SslMode;
+use sqlx::{Connection, PgConnection, PgPool};
+
+#[tokio::test]
+pub async fn test_pool_size_from_settings() -> Result<()> {
+    // spawn our app
+    let app = spawn_app_with(|c| c.db.max_connections = 3)
+        .await
+        .context("spawn testing app")?;
+
+    // scrape
+    let endpoint = format!("{}/metrics", app.metrics_address);
+    let body = app
+        .api_client
+        .get(endpoint)
+        .send()
+        .await
+        .context("send request")?
+        .text()
+        .await?;
+
+    assert!(
+        body.contains(r#"db_pool_connections{state="max"} 3"#),
+        "{body}"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_statement_timeout() -> Result<()> {
+    let mut settings = test_settings()?;
+    settings.db.database = "postgres".to_string();
+    settings.db.statement_timeout_ms = 100;
+
+    let pool = PgPool::connect_with(settings.db.get_db_settings())
+        .await
+        .context("connect to db")?;
+
+    let timeout: String = sqlx::query_scalar("SHOW statement_timeout")
+        .fetch_one(&pool)
+        .await?;
+    assert_eq!(timeout, "100ms");
+
+    // postgres cancels anything slower
+    let err = sqlx::query("SELECT pg_sleep(1)")
+        .execute(&pool)
+        .await
+        .expect_err("statement should time out");
+    let code = err.as_database_error().and_then(|e| e.code());
+    assert_eq!(code.as_deref(), Some("57014"));
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_ssl_modes() -> Result<()> {
+    // the test server doesn't speak tls
+    let mut settings = test_settings()?.db;
+    settings.database = "postgres".to_string();
+    settings.ssl = None;
+
+    for (mode, connects) in [
+        (SslMode::Disable, true),
+        (SslMode::Allow, true),
+        (SslMode::Prefer, true),
+        (SslMode::Require, false),
+        (SslMode::VerifyCa, false),
+        (SslMode::VerifyFull, false),
+    ] {
+        settings.ssl_mode = Some(mode);
+        let result = PgConnection::connect_with(&settings.get_db_settings()).await;
+        assert_eq!(result.is_ok(), connects, "{mode:?}");
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_legacy_ssl_flag() -> Result<()> {
+    let mut settings = test_settings()?.db;
+    settings.ssl_mode = None;
+
+    settings.ssl = Some(true);
+    assert_eq!(settings.ssl_mode(), SslMode::Require);
+    settings.ssl = Some(false);
+    assert_eq!(settings.ssl_mode(), SslMode::Disable);
+    settings.ssl = None;
+    assert_eq!(settings.ssl_mode(), SslMode::Prefer);
+
+    // the new setting wins
+    settings.ssl = Some(false);
+    settings.ssl_mode = Some(SslMode::VerifyFull);
+    assert_eq!(settings.ssl_mode(), SslMode::VerifyFull);
+
+    Ok(())
+}
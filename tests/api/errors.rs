@@ -0,0 +1,153 @@
+use crate::utils::spawn_app;
+use anyhow::Context;
+use anyhow::Result;
+use reqwest::StatusCode;
+use reqwest::header::{ALLOW, CONTENT_TYPE};
+use serde_json::Value;
+use uuid::Uuid;
+
+const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+/// checks the RFC 7807 shape of an error response and hands back the body
+async fn assert_problem(resp: reqwest::Response, status: StatusCode) -> Result<Value> {
+    assert_eq!(resp.status(), status);
+    assert_eq!(
+        resp.headers()
+            .get(CONTENT_TYPE)
+            .context("content type header")?,
+        PROBLEM_CONTENT_TYPE
+    );
+
+    let problem: Value = resp.json().await?;
+    assert_eq!(problem["type"], "about:blank");
+    assert_eq!(problem["status"], status.as_u16());
+    assert_eq!(
+        problem["title"],
+        status.canonical_reason().context("reason phrase")?
+    );
+    assert!(problem["detail"].is_string());
+    assert!(problem["request_id"].is_string());
+
+    Ok(problem)
+}
+
+#[tokio::test]
+pub async fn test_not_found_problem() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    // both a missing cat and a route that doesn't exist
+    let endpoints = vec![
+        format!("{}/v1/cats/{}", app.address, Uuid::new_v4()),
+        format!("{}/v1/dogs", app.address),
+    ];
+
+    for endpoint in endpoints {
+        let resp = app
+            .api_client
+            .get(endpoint)
+            .send()
+            .await
+            .context("send request")?;
+
+        assert_problem(resp, StatusCode::NOT_FOUND).await?;
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_method_not_allowed_problem() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    let resp = app
+        .api_client
+        .delete(format!("{}/v1/cats", app.address))
+        .send()
+        .await
+        .context("send request")?;
+
+    assert!(resp.headers().contains_key(ALLOW), "{resp:?}");
+    assert_problem(resp, StatusCode::METHOD_NOT_ALLOWED).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_path_rejection_problem() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    let endpoint = format!("{}/v1/cats/not-a-uuid", app.address);
+    let resp = app
+        .api_client
+        .get(endpoint)
+        .send()
+        .await
+        .context("send request")?;
+
+    let problem = assert_problem(resp, StatusCode::BAD_REQUEST).await?;
+    assert!(
+        problem["detail"]
+            .as_str()
+            .unwrap_or_default()
+            .contains("UUID")
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_json_rejection_problem() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    let endpoint = format!("{}/v1/cats", app.address);
+
+    // syntax error, wrong content type and a well formed body missing fields
+    let cases = vec![
+        ("application/json", "{", StatusCode::BAD_REQUEST),
+        ("text/plain", "{}", StatusCode::UNSUPPORTED_MEDIA_TYPE),
+        (
+            "application/json",
+            r#"{"name": "KITTY"}"#,
+            StatusCode::UNPROCESSABLE_ENTITY,
+        ),
+    ];
+
+    for (content_type, body, status) in cases {
+        let resp = app
+            .api_client
+            .post(&endpoint)
+            .header(CONTENT_TYPE, content_type)
+            .body(body)
+            .send()
+            .await
+            .context("send request")?;
+
+        assert_problem(resp, status).await?;
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_problem_uses_given_request_id() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    let endpoint = format!("{}/v1/cats/{}", app.address, Uuid::new_v4());
+    let resp = app
+        .api_client
+        .get(endpoint)
+        .header("X-Request-Id", "my-request")
+        .send()
+        .await
+        .context("send request")?;
+
+    let problem = assert_problem(resp, StatusCode::NOT_FOUND).await?;
+    assert_eq!(problem["request_id"], "my-request");
+
+    Ok(())
+}
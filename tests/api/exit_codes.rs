@@ -0,0 +1,110 @@
+use crate::utils::{binary, fresh_db_settings, wait_with_timeout};
+use anyhow::Context;
+use anyhow::Result;
+use std::net::TcpListener;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+#[tokio::test]
+pub async fn test_config_error_exit_code() -> Result<()> {
+    let mut child = binary(&[("APP_APPLICATION__PORT", "not a port")]).spawn()?;
+
+    assert_eq!(wait_with_timeout(&mut child, Duration::from_secs(10))?, 78);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_db_connection_error_exit_code() -> Result<()> {
+    // nothing listens on the port we just let go of
+    let port = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+    let port = port.to_string();
+    let child = binary(&[
+        ("APP_DB__PORT", &port),
+        ("APP_DB__ACQUIRE_TIMEOUT_MS", "200"),
+        ("APP_DB__CONNECT_RETRIES", "2"),
+        ("APP_DB__CONNECT_BACKOFF_INITIAL_MS", "50"),
+        ("APP_LOGGING__FORMAT", "json"),
+    ])
+    .stdout(Stdio::piped())
+    .spawn()?;
+
+    // it gives up after the retries are used up
+    let output = tokio::time::timeout(
+        Duration::from_secs(30),
+        tokio::task::spawn_blocking(|| child.wait_with_output()),
+    )
+    .await
+    .context("process didn't exit in time")???;
+    assert_eq!(output.status.code(), Some(69));
+
+    // and says which attempt it was on along the way
+    let logs = String::from_utf8(output.stdout)?;
+    for attempt in 1..=3 {
+        assert!(
+            logs.contains(&format!(r#""attempt":{attempt}"#)),
+            "attempt {attempt} missing from {logs}"
+        );
+    }
+    assert!(!logs.contains(r#""attempt":4"#));
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_bind_error_exit_code() -> Result<()> {
+    let database = fresh_db_settings(true).await?.database;
+
+    // hold on to the port so the app can't have it
+    let taken = TcpListener::bind("127.0.0.1:0")?;
+    let port = taken.local_addr()?.port().to_string();
+    let mut child = binary(&[
+        ("APP_DB__DATABASE", &database),
+        ("APP_APPLICATION__HOST", "127.0.0.1"),
+        ("APP_APPLICATION__PORT", &port),
+    ])
+    .spawn()?;
+
+    assert_eq!(wait_with_timeout(&mut child, Duration::from_secs(30))?, 71);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_clean_shutdown_exit_code() -> Result<()> {
+    let database = fresh_db_settings(true).await?.database;
+
+    let port = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+    let port_str = port.to_string();
+    let mut child = binary(&[
+        ("APP_DB__DATABASE", &database),
+        ("APP_APPLICATION__HOST", "127.0.0.1"),
+        ("APP_APPLICATION__PORT", &port_str),
+    ])
+    .spawn()?;
+
+    // wait until it's serving
+    let client = reqwest::Client::new();
+    let started = Instant::now();
+    while client
+        .get(format!("http://127.0.0.1:{port}/health"))
+        .send()
+        .await
+        .is_err()
+    {
+        if started.elapsed() > Duration::from_secs(30) {
+            child.kill()?;
+            anyhow::bail!("app never started serving");
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    let status = Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()?;
+    assert!(status.success());
+
+    assert_eq!(wait_with_timeout(&mut child, Duration::from_secs(30))?, 0);
+
+    Ok(())
+}
@@ -1,7 +1,8 @@
-use crate::utils::spawn_app;
+use crate::utils::{spawn_app, start_app};
 use anyhow::Context;
 use anyhow::Result;
 use axum::http::StatusCode;
+use serde_json::Value;
 
 #[tokio::test]
 pub async fn test_health() -> Result<()> {
@@ -22,3 +23,129 @@ pub async fn test_health() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+pub async fn test_live() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    // send the request
+    let endpoint = format!("{}/health/live", app.address);
+    let resp = app
+        .api_client
+        .get(endpoint)
+        .send()
+        .await
+        .context("send request")?;
+
+    // check status
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_ready() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    // send the request
+    let endpoint = format!("{}/health/ready", app.address);
+    let resp = app
+        .api_client
+        .get(endpoint)
+        .send()
+        .await
+        .context("send request")?;
+
+    // check status
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let report: Value = resp.json().await?;
+    assert_eq!(report["status"], "pass");
+    for check in report["checks"].as_array().context("checks")? {
+        assert_eq!(check["status"], "pass", "{check}");
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_ready_migration_mismatch() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    // pretend the latest migration never ran
+    sqlx::query(
+        "DELETE FROM _sqlx_migrations WHERE version = (SELECT MAX(version) FROM _sqlx_migrations)",
+    )
+    .execute(&app.db_pool)
+    .await?;
+
+    let endpoint = format!("{}/health/ready", app.address);
+    let resp = app
+        .api_client
+        .get(endpoint)
+        .send()
+        .await
+        .context("send request")?;
+
+    assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    let report: Value = resp.json().await?;
+    assert_eq!(report["status"], "fail");
+    let migrations = report["checks"]
+        .as_array()
+        .context("checks")?
+        .iter()
+        .find(|c| c["name"] == "migrations")
+        .context("migrations check")?;
+    assert_eq!(migrations["status"], "fail");
+    assert!(
+        migrations["detail"]
+            .as_str()
+            .unwrap_or_default()
+            .contains("has not been applied")
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_ready_with_newer_migrations() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    // a newer release has migrated the db ahead of us, as in a rolling deploy
+    sqlx::query(
+        "INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time)
+        VALUES (99991231000000, 'from the future', TRUE, '\\x00', 0)",
+    )
+    .execute(&app.db_pool)
+    .await?;
+
+    let endpoint = format!("{}/health/ready", app.address);
+    let resp = app
+        .api_client
+        .get(endpoint)
+        .send()
+        .await
+        .context("send request")?;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // and one of us restarting, migrating on start, comes back up too
+    assert!(app.settings.db.migrate_on_start);
+    let replica = start_app(app.settings.clone())
+        .await
+        .context("restart with the db ahead of us")?;
+    let resp = replica
+        .api_client
+        .get(format!("{}/health/ready", replica.address))
+        .send()
+        .await
+        .context("send request")?;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    Ok(())
+}
@@ -0,0 +1,113 @@
+use crate::utils::{spawn_app, spawn_app_with};
+use anyhow::Context;
+use anyhow::Result;
+use reqwest::{StatusCode, Version};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// What a client opens an http/2 connection with when it skips the upgrade
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+const SETTINGS_FRAME: u8 = 0x4;
+const SETTINGS_MAX_CONCURRENT_STREAMS: u16 = 0x3;
+
+#[tokio::test]
+pub async fn test_http1() -> Result<()> {
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    let client = reqwest::Client::builder().http1_only().build()?;
+    let resp = client
+        .get(format!("{}/health", app.address))
+        .send()
+        .await
+        .context("send request")?;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.version(), Version::HTTP_11);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_h2c_prior_knowledge() -> Result<()> {
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    // http/2 over plain tcp, the way a mesh sidecar talks to us
+    let client = reqwest::Client::builder().http2_prior_knowledge().build()?;
+    let resp = client
+        .get(format!("{}/health", app.address))
+        .send()
+        .await
+        .context("send request")?;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.version(), Version::HTTP_2);
+
+    // several requests share the connection
+    let requests: Vec<_> = (0..10)
+        .map(|_| tokio::spawn(client.get(format!("{}/health", app.address)).send()))
+        .collect();
+    for request in requests {
+        let resp = request.await??;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.version(), Version::HTTP_2);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_max_concurrent_streams_advertised() -> Result<()> {
+    let app = spawn_app_with(|c| c.application.http.http2_max_concurrent_streams = 7)
+        .await
+        .context("spawn testing app")?;
+
+    // the server's first frame is its settings
+    let mut stream = TcpStream::connect(app.address.trim_start_matches("http://")).await?;
+    stream.write_all(PREFACE).await?;
+    stream
+        .write_all(&[0, 0, 0, SETTINGS_FRAME, 0, 0, 0, 0, 0])
+        .await?;
+
+    let mut header = [0; 9];
+    stream.read_exact(&mut header).await?;
+    assert_eq!(header[3], SETTINGS_FRAME);
+    let len = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+    let mut payload = vec![0; len];
+    stream.read_exact(&mut payload).await?;
+
+    let max_streams = payload
+        .chunks_exact(6)
+        .find(|setting| {
+            u16::from_be_bytes([setting[0], setting[1]]) == SETTINGS_MAX_CONCURRENT_STREAMS
+        })
+        .map(|setting| u32::from_be_bytes([setting[2], setting[3], setting[4], setting[5]]))
+        .context("max concurrent streams setting")?;
+    assert_eq!(max_streams, 7);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_http1_keep_alive_disabled() -> Result<()> {
+    let app = spawn_app_with(|c| c.application.http.http1_keep_alive = false)
+        .await
+        .context("spawn testing app")?;
+
+    let client = reqwest::Client::builder().http1_only().build()?;
+    let resp = client
+        .get(format!("{}/health", app.address))
+        .send()
+        .await
+        .context("send request")?;
+
+    // the server hangs up after every response
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers()
+            .get(reqwest::header::CONNECTION)
+            .context("connection header")?,
+        "close"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,417 @@
+use crate::utils::{TestApp, spawn_app_with};
+use anyhow::Context;
+use anyhow::Result;
+use axum::extract::State;
+use axum::routing::get;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use gha_demo::settings::Settings;
+use gha_demo::types::v1::types::{Cat, EyeColor};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use rcgen::KeyPair;
+use reqwest::StatusCode;
+use reqwest::header::RETRY_AFTER;
+use serde_json::{Value, json};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+const ISSUER: &str = "https://sso.example.com";
+const AUDIENCE: &str = "cats-api";
+
+/// A P-256 signing key, as the SSO would hold it
+struct SigningKey {
+    kid: String,
+    key_pair: KeyPair,
+}
+
+impl SigningKey {
+    fn generate(kid: &str) -> Result<Self> {
+        Ok(Self {
+            kid: kid.to_string(),
+            key_pair: KeyPair::generate()?,
+        })
+    }
+
+    /// The public half, as published in the jwks
+    fn jwk(&self) -> Value {
+        // an uncompressed point, 0x04 then x then y
+        let point = self.key_pair.public_key_raw();
+        json!({
+            "kty": "EC",
+            "crv": "P-256",
+            "use": "sig",
+            "alg": "ES256",
+            "kid": self.kid,
+            "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+            "y": URL_SAFE_NO_PAD.encode(&point[33..65]),
+        })
+    }
+
+    fn sign(&self, claims: &Value) -> Result<String> {
+        let mut header = Header::new(Algorithm::ES256);
+        header.kid = Some(self.kid.clone());
+        let key = EncodingKey::from_ec_der(&self.key_pair.serialize_der());
+        Ok(jsonwebtoken::encode(&header, claims, &key)?)
+    }
+
+    /// A token that passes every check, carrying `scope`
+    fn token(&self, scope: &str) -> Result<String> {
+        self.sign(&claims(scope))
+    }
+}
+
+fn jwks(keys: &[&SigningKey]) -> Value {
+    json!({ "keys": keys.iter().map(|key| key.jwk()).collect::<Vec<_>>() })
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("after the epoch")
+        .as_secs()
+}
+
+fn claims(scope: &str) -> Value {
+    json!({
+        "iss": ISSUER,
+        "aud": AUDIENCE,
+        "sub": "someone@example.com",
+        "exp": now() + 300,
+        "scope": scope,
+    })
+}
+
+fn write_jwks(jwks: &Value) -> Result<PathBuf> {
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("{}.json", Uuid::new_v4()));
+    std::fs::write(&path, serde_json::to_vec(jwks)?)?;
+    Ok(path)
+}
+
+fn enable_jwt(c: &mut Settings) {
+    c.auth.jwt.enabled = true;
+    c.auth.jwt.issuer = ISSUER.to_string();
+    c.auth.jwt.audience = AUDIENCE.to_string();
+}
+
+async fn create_cat(app: &TestApp, token: &str) -> Result<reqwest::Response> {
+    let cat = Cat {
+        name: "Tom".to_string(),
+        cool_cat_club_id: Uuid::new_v4(),
+        age: 3,
+        eye_color: EyeColor::Blue,
+    };
+    reqwest::Client::new()
+        .post(format!("{}/v1/cats", app.address))
+        .bearer_auth(token)
+        .json(&cat)
+        .send()
+        .await
+        .context("send request")
+}
+
+async fn detail(resp: reqwest::Response) -> Result<String> {
+    let problem: Value = resp.json().await?;
+    problem["detail"]
+        .as_str()
+        .map(str::to_string)
+        .context("problem detail")
+}
+
+#[tokio::test]
+pub async fn test_jwt_from_jwks_file() -> Result<()> {
+    let key = SigningKey::generate("k1")?;
+    let path = write_jwks(&jwks(&[&key]))?;
+    let app = spawn_app_with(|c| {
+        enable_jwt(c);
+        c.auth.jwt.jwks_path = Some(path.clone());
+    })
+    .await
+    .context("spawn testing app")?;
+
+    let resp = create_cat(&app, &key.token("openid cats:read cats:write")?).await?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    // api keys keep working alongside
+    let resp = create_cat(&app, &app.api_key).await?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_jwt_scopes() -> Result<()> {
+    let key = SigningKey::generate("k1")?;
+    let path = write_jwks(&jwks(&[&key]))?;
+    let app = spawn_app_with(|c| {
+        enable_jwt(c);
+        c.auth.jwt.jwks_path = Some(path.clone());
+        c.auth.public_reads = false;
+    })
+    .await
+    .context("spawn testing app")?;
+
+    // reading doesn't let you write
+    let token = key.token("cats:read")?;
+    let resp = create_cat(&app, &token).await?;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    assert_eq!(detail(resp).await?, "the token lacks the cats:write scope");
+
+    let resp = reqwest::Client::new()
+        .get(format!("{}/v1/cats", app.address))
+        .bearer_auth(&token)
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // nor the other way around, with the scopes as a list this time
+    let mut claims = claims("");
+    claims["scope"] = Value::Null;
+    claims["scp"] = json!(["cats:write"]);
+    let resp = reqwest::Client::new()
+        .get(format!("{}/v1/cats", app.address))
+        .bearer_auth(key.sign(&claims)?)
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_invalid_jwts_are_rejected() -> Result<()> {
+    let key = SigningKey::generate("k1")?;
+    // published as being for another algorithm than it signs with
+    let mislabelled = SigningKey::generate("k3")?;
+    let mut published = jwks(&[&key, &mislabelled]);
+    published["keys"][1]["alg"] = json!("ES384");
+    let path = write_jwks(&published)?;
+    let app = spawn_app_with(|c| {
+        enable_jwt(c);
+        c.auth.jwt.jwks_path = Some(path.clone());
+    })
+    .await
+    .context("spawn testing app")?;
+
+    let with = |field: &str, value: Value| {
+        let mut claims = claims("cats:write");
+        claims[field] = value;
+        claims
+    };
+    let unpublished = SigningKey::generate("k2")?;
+    let impostor = SigningKey {
+        kid: "k1".to_string(),
+        key_pair: KeyPair::generate()?,
+    };
+    let hmac = jsonwebtoken::encode(
+        &Header::new(Algorithm::HS256),
+        &claims("cats:write"),
+        &EncodingKey::from_secret(b"shared secret"),
+    )?;
+
+    for (token, expected) in [
+        (
+            key.sign(&with("aud", json!("another-api")))?,
+            "the token is meant for another audience",
+        ),
+        (
+            key.sign(&with("iss", json!("https://evil.example.com")))?,
+            "the token is from an untrusted issuer",
+        ),
+        (
+            // well past the leeway
+            key.sign(&with("exp", json!(now() - 600)))?,
+            "the token has expired",
+        ),
+        (
+            key.sign(&with("nbf", json!(now() + 600)))?,
+            "the token isn't valid yet",
+        ),
+        (
+            unpublished.token("cats:write")?,
+            "the token is signed by an unknown key",
+        ),
+        (
+            impostor.token("cats:write")?,
+            "the token's signature doesn't match",
+        ),
+        (
+            mislabelled.token("cats:write")?,
+            "the token is signed with ES256, but its key is for ES384",
+        ),
+        (hmac, "the token is signed with HS256, which isn't accepted"),
+        ("not.a.jwt".to_string(), "the token is malformed"),
+    ] {
+        let resp = create_cat(&app, &token).await?;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(detail(resp).await?, expected);
+    }
+
+    Ok(())
+}
+
+/// Serves whatever jwks is current, counting how often it's fetched
+#[derive(Clone, Default)]
+struct JwksServer {
+    jwks: Arc<Mutex<Value>>,
+    fetches: Arc<AtomicUsize>,
+}
+
+impl JwksServer {
+    async fn start(jwks: Value) -> Result<(Self, String)> {
+        let server = Self::default();
+        *server.jwks.lock().expect("jwks lock") = jwks;
+
+        let router = axum::Router::new()
+            .route(
+                "/.well-known/jwks.json",
+                get(|State(server): State<JwksServer>| async move {
+                    server.fetches.fetch_add(1, Ordering::SeqCst);
+                    axum::Json(server.jwks.lock().expect("jwks lock").clone())
+                }),
+            )
+            .with_state(server.clone());
+        let listener = tokio::net::TcpListener::bind("localhost:0").await?;
+        let url = format!(
+            "http://localhost:{}/.well-known/jwks.json",
+            listener.local_addr()?.port()
+        );
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        Ok((server, url))
+    }
+
+    fn publish(&self, jwks: Value) {
+        *self.jwks.lock().expect("jwks lock") = jwks;
+    }
+
+    fn fetches(&self) -> usize {
+        self.fetches.load(Ordering::SeqCst)
+    }
+}
+
+#[tokio::test]
+pub async fn test_jwks_url_with_rotation() -> Result<()> {
+    let old = SigningKey::generate("old")?;
+    let new = SigningKey::generate("new")?;
+    let (server, url) = JwksServer::start(jwks(&[&old])).await?;
+    let app = spawn_app_with(|c| {
+        enable_jwt(c);
+        c.auth.jwt.jwks_url = Some(url);
+        c.auth.jwt.jwks_min_refresh_interval_ms = 0;
+    })
+    .await
+    .context("spawn testing app")?;
+
+    // fetched once on startup, then served from the cache
+    let resp = create_cat(&app, &old.token("cats:write")?).await?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let resp = create_cat(&app, &old.token("cats:write")?).await?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    assert_eq!(server.fetches(), 1);
+
+    // the sso rotates, and the first token signed with the new key fetches it
+    server.publish(jwks(&[&new]));
+    let resp = create_cat(&app, &new.token("cats:write")?).await?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    assert_eq!(server.fetches(), 2);
+
+    // the old key is gone
+    let resp = create_cat(&app, &old.token("cats:write")?).await?;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_unknown_keys_dont_hammer_the_jwks() -> Result<()> {
+    let key = SigningKey::generate("k1")?;
+    let (server, url) = JwksServer::start(jwks(&[&key])).await?;
+    let app = spawn_app_with(|c| {
+        enable_jwt(c);
+        c.auth.jwt.jwks_url = Some(url);
+    })
+    .await
+    .context("spawn testing app")?;
+
+    for i in 0..5 {
+        let made_up = SigningKey::generate(&format!("made-up-{i}"))?;
+        let resp = create_cat(&app, &made_up.token("cats:write")?).await?;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+    assert_eq!(server.fetches(), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_jwks_down_at_startup_is_retried_sparingly() -> Result<()> {
+    let key = SigningKey::generate("k1")?;
+    // nothing usable is published yet
+    let (server, url) = JwksServer::start(json!({ "keys": [] })).await?;
+    let app = spawn_app_with(|c| {
+        enable_jwt(c);
+        c.auth.jwt.jwks_url = Some(url);
+        c.auth.jwt.jwks_min_refresh_interval_ms = 500;
+    })
+    .await
+    .context("spawn testing app")?;
+    assert_eq!(server.fetches(), 1);
+
+    // there's no key to check against, and no point asking again right away
+    for _ in 0..5 {
+        let resp = create_cat(&app, &key.token("cats:write")?).await?;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            resp.headers()
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok()),
+            Some("1")
+        );
+    }
+    assert_eq!(server.fetches(), 1);
+
+    // once the backoff is up the next token fetches again
+    server.publish(jwks(&[&key]));
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    let resp = create_cat(&app, &key.token("cats:write")?).await?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    assert_eq!(server.fetches(), 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_stale_keys_are_served_while_refreshing() -> Result<()> {
+    let key = SigningKey::generate("k1")?;
+    let (server, url) = JwksServer::start(jwks(&[&key])).await?;
+    let app = spawn_app_with(|c| {
+        enable_jwt(c);
+        c.auth.jwt.jwks_url = Some(url);
+        c.auth.jwt.jwks_refresh_interval_ms = 1;
+        c.auth.jwt.jwks_min_refresh_interval_ms = 0;
+    })
+    .await
+    .context("spawn testing app")?;
+
+    // the provider goes away, but the keys we have are still good
+    server.publish(json!({ "keys": [] }));
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    for _ in 0..3 {
+        let resp = create_cat(&app, &key.token("cats:write")?).await?;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+    }
+
+    // the refetching happened off to the side
+    for _ in 0..100 {
+        if server.fetches() > 1 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(server.fetches() > 1);
+
+    Ok(())
+}
@@ -1,3 +1,19 @@
+mod auth;
 mod cats;
+mod cats_v2;
+mod cli;
+mod db;
+mod errors;
+mod exit_codes;
 mod health;
+mod http;
+mod jwt;
+mod metrics;
+mod migrate;
+mod openapi;
+mod rate_limit;
+mod request_id;
+mod secrets;
+mod shutdown;
+mod tls;
 mod utils;
@@ -0,0 +1,68 @@
+use crate::utils::spawn_app;
+use anyhow::Context;
+use anyhow::Result;
+use reqwest::StatusCode;
+use uuid::Uuid;
+
+#[tokio::test]
+pub async fn test_metrics() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    // generate some traffic, one success and one failure
+    let endpoints = vec![
+        format!("{}/v1/cats", app.address),
+        format!("{}/v1/cats/{}", app.address, Uuid::new_v4()),
+    ];
+    for endpoint in endpoints {
+        app.api_client
+            .get(endpoint)
+            .send()
+            .await
+            .context("send request")?;
+    }
+
+    // scrape
+    let endpoint = format!("{}/metrics", app.metrics_address);
+    let resp = app
+        .api_client
+        .get(endpoint)
+        .send()
+        .await
+        .context("send request")?;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = resp.text().await?;
+
+    // labelled by the route template, not the concrete path
+    assert!(body.contains(r#"http_requests_total{method="GET",route="/v1/cats",status="200"} 1"#));
+    assert!(body.contains(
+        r#"http_requests_total{method="GET",route="/v1/cats/{cool_cat_club_id}",status="404"} 1"#
+    ));
+    assert!(body.contains(r#"http_request_duration_seconds_bucket{method="GET",route="/v1/cats""#));
+    assert!(body.contains(r#"db_pool_connections{state="max"}"#));
+    assert!(body.contains(r#"db_pool_connections{state="idle"}"#));
+    // nothing is queueing for a connection once the requests are done
+    assert!(body.contains(r#"db_pool_connections{state="waiting"} 0"#));
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_metrics_not_public() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    // the api listener shouldn't serve metrics
+    let endpoint = format!("{}/metrics", app.address);
+    let resp = app
+        .api_client
+        .get(endpoint)
+        .send()
+        .await
+        .context("send request")?;
+
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
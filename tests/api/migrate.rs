@@ -0,0 +1,203 @@
+use crate::utils::{binary, fresh_db_settings, wait_with_timeout};
+use anyhow::Context;
+use anyhow::Result;
+use gha_demo::settings::DbSettings;
+use reqwest::StatusCode;
+use sqlx::PgPool;
+use std::net::TcpListener;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+
+const VERSIONS: [i64; 5] = [
+    20250822153422,
+    20261018090000,
+    20261018100000,
+    20261018110000,
+    20261018120000,
+];
+
+/// Runs `gha_demo migrate <args>` against `db`, returning what it printed
+async fn migrate(db: &DbSettings, args: &[&str]) -> Result<(i32, String)> {
+    let child = binary(&[("APP_DB__DATABASE", &db.database)])
+        .arg("migrate")
+        .args(args)
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    let output = tokio::time::timeout(
+        Duration::from_secs(30),
+        tokio::task::spawn_blocking(|| child.wait_with_output()),
+    )
+    .await
+    .context("migrate didn't exit in time")???;
+
+    Ok((
+        output.status.code().context("exited by signal")?,
+        String::from_utf8(output.stdout)?,
+    ))
+}
+
+/// The state `migrate status` printed for `version`
+fn state_of(output: &str, version: i64) -> Option<&str> {
+    output
+        .lines()
+        .find(|line| line.starts_with(&version.to_string()))
+        .and_then(|line| line.split_whitespace().nth(1))
+}
+
+async fn applied_versions(db: &DbSettings) -> Result<Vec<i64>> {
+    let pool = PgPool::connect_with(db.get_db_settings()).await?;
+    let versions = sqlx::query_scalar("SELECT version FROM _sqlx_migrations ORDER BY version")
+        .fetch_all(&pool)
+        .await?;
+    Ok(versions)
+}
+
+#[tokio::test]
+pub async fn test_migrate_status_run_and_revert() -> Result<()> {
+    let db = fresh_db_settings(false).await?;
+
+    // nothing applied yet, and looking doesn't change that
+    let (code, output) = migrate(&db, &["status"]).await?;
+    assert_eq!(code, 0);
+    assert_eq!(output.lines().next(), Some("no migrations applied"));
+    for version in VERSIONS {
+        assert_eq!(state_of(&output, version), Some("pending"), "{output}");
+    }
+    let pool = PgPool::connect_with(db.get_db_settings()).await?;
+    let table: Option<String> = sqlx::query_scalar("SELECT to_regclass('_sqlx_migrations')::text")
+        .fetch_one(&pool)
+        .await?;
+    assert_eq!(table, None);
+
+    // stdout is the table and nothing else, logs go to stderr
+    assert_eq!(output.lines().count(), VERSIONS.len() + 1, "{output}");
+
+    // apply everything
+    let (code, output) = migrate(&db, &["run"]).await?;
+    assert_eq!(code, 0);
+    for version in VERSIONS {
+        assert_eq!(state_of(&output, version), Some("applied"), "{output}");
+    }
+    assert_eq!(applied_versions(&db).await?, VERSIONS);
+
+    // back to the first one
+    let (code, output) = migrate(&db, &["revert", &VERSIONS[0].to_string()]).await?;
+    assert_eq!(code, 0);
+    assert_eq!(state_of(&output, VERSIONS[0]), Some("applied"), "{output}");
+    assert_eq!(state_of(&output, VERSIONS[1]), Some("pending"), "{output}");
+    assert_eq!(state_of(&output, VERSIONS[2]), Some("pending"), "{output}");
+    assert_eq!(state_of(&output, VERSIONS[3]), Some("pending"), "{output}");
+    assert_eq!(applied_versions(&db).await?, VERSIONS[..1]);
+
+    // and all the way back
+    let (code, _) = migrate(&db, &["revert", "0"]).await?;
+    assert_eq!(code, 0);
+    assert!(applied_versions(&db).await?.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_check_constraints_quarantine_invalid_cats() -> Result<()> {
+    let db = fresh_db_settings(true).await?;
+    let pool = PgPool::connect_with(db.get_db_settings()).await?;
+
+    // back to before the constraints, when anything went
+    let (code, _) = migrate(&db, &["revert", &VERSIONS[1].to_string()]).await?;
+    assert_eq!(code, 0);
+    sqlx::query(
+        "INSERT INTO cats (name, cool_cat_club_id, age, eye_color) VALUES
+            ('Tom', gen_random_uuid(), 3, 'Blue'),
+            ('  Padded  ', gen_random_uuid(), 3, 'Blue'),
+            ('', gen_random_uuid(), 3, 'Blue'),
+            ('Old Timer', gen_random_uuid(), 99, 'Brown'),
+            ('Nobody', '00000000-0000-0000-0000-000000000000', 3, 'Blue')",
+    )
+    .execute(&pool)
+    .await?;
+
+    let (code, _) = migrate(&db, &["run"]).await?;
+    assert_eq!(code, 0);
+
+    let mut kept: Vec<String> = sqlx::query_scalar("SELECT name FROM cats")
+        .fetch_all(&pool)
+        .await?;
+    kept.sort();
+    assert_eq!(kept, ["Padded", "Tom"]);
+    let quarantined: i64 = sqlx::query_scalar("SELECT count(*) FROM cats_quarantine")
+        .fetch_one(&pool)
+        .await?;
+    assert_eq!(quarantined, 3);
+
+    // and they come back on the way down
+    let (code, _) = migrate(&db, &["revert", &VERSIONS[1].to_string()]).await?;
+    assert_eq!(code, 0);
+    let count: i64 = sqlx::query_scalar("SELECT count(*) FROM cats")
+        .fetch_one(&pool)
+        .await?;
+    assert_eq!(count, 5);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_migrate_revert_unknown_version() -> Result<()> {
+    let db = fresh_db_settings(true).await?;
+
+    let (code, _) = migrate(&db, &["revert", "12345"]).await?;
+    assert_eq!(code, 65);
+
+    // nothing was touched
+    assert_eq!(applied_versions(&db).await?, VERSIONS);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_serve_without_migrating() -> Result<()> {
+    let db = fresh_db_settings(false).await?;
+
+    let port = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+    let port_str = port.to_string();
+    let mut child = binary(&[
+        ("APP_DB__DATABASE", &db.database),
+        ("APP_DB__MIGRATE_ON_START", "false"),
+        ("APP_APPLICATION__HOST", "127.0.0.1"),
+        ("APP_APPLICATION__PORT", &port_str),
+    ])
+    .spawn()?;
+
+    // wait until it's serving
+    let client = reqwest::Client::new();
+    let started = Instant::now();
+    while client
+        .get(format!("http://127.0.0.1:{port}/health/live"))
+        .send()
+        .await
+        .is_err()
+    {
+        if started.elapsed() > Duration::from_secs(30) {
+            child.kill()?;
+            anyhow::bail!("app never started serving");
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    // it runs, but isn't ready for traffic without its schema
+    let resp = client
+        .get(format!("http://127.0.0.1:{port}/health/ready"))
+        .send()
+        .await?;
+    child.kill()?;
+    wait_with_timeout(&mut child, Duration::from_secs(10)).ok();
+
+    assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let pool = PgPool::connect_with(db.get_db_settings()).await?;
+    let cats_table: Option<String> = sqlx::query_scalar("SELECT to_regclass('cats')::text")
+        .fetch_one(&pool)
+        .await?;
+    assert_eq!(cats_table, None);
+
+    Ok(())
+}
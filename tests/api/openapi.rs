@@ -0,0 +1,61 @@
+use crate::utils::spawn_app;
+use anyhow::Context;
+use anyhow::Result;
+use reqwest::StatusCode;
+use serde_json::Value;
+
+const COMMITTED_SPEC: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/openapi.json");
+
+#[tokio::test]
+pub async fn test_openapi_matches_committed_spec() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    // send the request
+    let endpoint = format!("{}/openapi.json", app.address);
+    let resp = app
+        .api_client
+        .get(endpoint)
+        .send()
+        .await
+        .context("send request")?;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    let served: Value = resp.json().await?;
+    assert_eq!(served["openapi"], "3.1.0");
+
+    // regenerate instead of comparing when asked to
+    if std::env::var("UPDATE_OPENAPI").is_ok() {
+        let pretty = serde_json::to_string_pretty(&served)? + "\n";
+        std::fs::write(COMMITTED_SPEC, pretty).context("write openapi spec")?;
+        return Ok(());
+    }
+
+    let committed = std::fs::read_to_string(COMMITTED_SPEC).context("read openapi spec")?;
+    let committed: Value = serde_json::from_str(&committed)?;
+    assert!(
+        served == committed,
+        "openapi.json is out of date, rerun the tests with UPDATE_OPENAPI=1 and commit the result"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_docs_page() -> Result<()> {
+    // spawn our app, the local config turns the docs on
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    let endpoint = format!("{}/docs", app.address);
+    let resp = app
+        .api_client
+        .get(endpoint)
+        .send()
+        .await
+        .context("send request")?;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(resp.text().await?.contains("/openapi.json"));
+
+    Ok(())
+}
@@ -0,0 +1,291 @@
+use crate::utils::{TestApp, spawn_app_with, start_app};
+use anyhow::Context;
+use anyhow::Result;
+use gha_demo::api_keys::ApiKey;
+use gha_demo::auth::Scope;
+use gha_demo::settings::{RateLimitBackend, RouteLimit, Settings};
+use gha_demo::types::v1::types::{Cat, EyeColor};
+use reqwest::StatusCode;
+use reqwest::header::{CONTENT_TYPE, RETRY_AFTER};
+use serde_json::Value;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// `burst` requests, then one every 100s, which no test waits for
+fn slow(burst: u32) -> RouteLimit {
+    RouteLimit {
+        burst,
+        per_second: 0.01,
+    }
+}
+
+fn limit_cats(c: &mut Settings, burst: u32) {
+    c.rate_limit.enabled = true;
+    c.rate_limit.cats = slow(burst);
+}
+
+async fn get(app: &TestApp, path: &str) -> Result<reqwest::Response> {
+    reqwest::Client::new()
+        .get(format!("{}{path}", app.address))
+        .send()
+        .await
+        .context("send request")
+}
+
+async fn get_forwarded_for(app: &TestApp, forwarded_for: &str) -> Result<StatusCode> {
+    let resp = reqwest::Client::new()
+        .get(format!("{}/v1/cats", app.address))
+        .header("x-forwarded-for", forwarded_for)
+        .send()
+        .await
+        .context("send request")?;
+    Ok(resp.status())
+}
+
+fn header<'a>(resp: &'a reqwest::Response, name: &str) -> Option<&'a str> {
+    resp.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+}
+
+#[tokio::test]
+pub async fn test_clients_are_limited_per_route_group() -> Result<()> {
+    let app = spawn_app_with(|c| {
+        limit_cats(c, 3);
+        c.rate_limit.latency = slow(1);
+    })
+    .await
+    .context("spawn testing app")?;
+
+    for remaining in ["2", "1", "0"] {
+        let resp = get(&app, "/v1/cats").await?;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(header(&resp, "ratelimit-limit"), Some("3"));
+        assert_eq!(header(&resp, "ratelimit-remaining"), Some(remaining));
+        assert_eq!(header(&resp, "ratelimit-policy"), Some("3;w=300"));
+    }
+
+    let resp = get(&app, "/v1/cats").await?;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(header(&resp, RETRY_AFTER.as_str()), Some("100"));
+    assert_eq!(header(&resp, "ratelimit-remaining"), Some("0"));
+    assert_eq!(header(&resp, "ratelimit-reset"), Some("300"));
+    assert_eq!(
+        header(&resp, CONTENT_TYPE.as_str()),
+        Some("application/problem+json")
+    );
+    let problem: Value = resp.json().await?;
+    assert_eq!(problem["status"], 429);
+    assert_eq!(problem["detail"], "too many requests, try again in 100s");
+
+    // v2 draws from the same bucket
+    let resp = get(&app, "/v2/cats").await?;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    // but latency has its own
+    let resp = get(&app, "/latency").await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(header(&resp, "ratelimit-limit"), Some("1"));
+    let resp = get(&app, "/latency").await?;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    // and the rest isn't limited at all
+    let resp = get(&app, "/health/live").await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(header(&resp, "ratelimit-limit"), None);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_buckets_refill() -> Result<()> {
+    let app = spawn_app_with(|c| {
+        c.rate_limit.enabled = true;
+        c.rate_limit.cats = RouteLimit {
+            burst: 1,
+            per_second: 10.0,
+        }
+    })
+    .await
+    .context("spawn testing app")?;
+
+    assert_eq!(get(&app, "/v1/cats").await?.status(), StatusCode::OK);
+    let resp = get(&app, "/v1/cats").await?;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(header(&resp, RETRY_AFTER.as_str()), Some("1"));
+
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    assert_eq!(get(&app, "/v1/cats").await?.status(), StatusCode::OK);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_api_keys_get_their_own_bucket() -> Result<()> {
+    let app = spawn_app_with(|c| limit_cats(c, 3))
+        .await
+        .context("spawn testing app")?;
+    let made_up_key = || async {
+        reqwest::Client::new()
+            .get(format!("{}/v1/cats", app.address))
+            .bearer_auth("ccc_not-a-real-key")
+            .send()
+            .await
+            .context("send request")
+    };
+
+    // made up keys count against the ip
+    assert_eq!(made_up_key().await?.status(), StatusCode::UNAUTHORIZED);
+
+    // a key we haven't seen pays the ip once, then has a bucket of its own,
+    // and is still good for writing after the limiter looked it up
+    let cat = Cat {
+        name: "Tom".to_string(),
+        cool_cat_club_id: Uuid::new_v4(),
+        age: 3,
+        eye_color: EyeColor::Blue,
+    };
+    let resp = app
+        .api_client
+        .post(format!("{}/v1/cats", app.address))
+        .json(&cat)
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    for status in [
+        StatusCode::OK,
+        StatusCode::OK,
+        StatusCode::TOO_MANY_REQUESTS,
+    ] {
+        let resp = app
+            .api_client
+            .get(format!("{}/v1/cats", app.address))
+            .send()
+            .await?;
+        assert_eq!(resp.status(), status);
+    }
+
+    // someone else from the same ip isn't held back by it, but their key
+    // takes the ip's last request
+    let (_, key) = ApiKey::mint(&app.db_pool, "other", &Scope::ALL).await?;
+    let resp = reqwest::Client::new()
+        .get(format!("{}/v1/cats", app.address))
+        .bearer_auth(&key)
+        .send()
+        .await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        get(&app, "/v1/cats").await?.status(),
+        StatusCode::TOO_MANY_REQUESTS
+    );
+
+    // and once that's gone made up keys are turned away before they're
+    // looked up
+    assert_eq!(made_up_key().await?.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_forwarded_for_from_trusted_proxies() -> Result<()> {
+    let app = spawn_app_with(|c| {
+        limit_cats(c, 1);
+        c.rate_limit.trusted_proxies = vec!["127.0.0.0/8".to_string(), "::1".to_string()];
+    })
+    .await
+    .context("spawn testing app")?;
+
+    assert_eq!(
+        get_forwarded_for(&app, "203.0.113.7").await?,
+        StatusCode::OK
+    );
+    assert_eq!(
+        get_forwarded_for(&app, "203.0.113.7").await?,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+    assert_eq!(
+        get_forwarded_for(&app, "203.0.113.8").await?,
+        StatusCode::OK
+    );
+
+    // whatever the client put in front is ignored
+    assert_eq!(
+        get_forwarded_for(&app, "198.51.100.1, 203.0.113.7").await?,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+
+    // hops through our own proxies are skipped
+    assert_eq!(
+        get_forwarded_for(&app, "203.0.113.9, 127.0.0.2").await?,
+        StatusCode::OK
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_forwarded_for_from_anyone_else_is_ignored() -> Result<()> {
+    let app = spawn_app_with(|c| limit_cats(c, 1))
+        .await
+        .context("spawn testing app")?;
+
+    assert_eq!(
+        get_forwarded_for(&app, "203.0.113.7").await?,
+        StatusCode::OK
+    );
+    assert_eq!(
+        get_forwarded_for(&app, "203.0.113.8").await?,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_postgres_limits_are_shared_across_replicas() -> Result<()> {
+    let app = spawn_app_with(|c| {
+        limit_cats(c, 3);
+        c.rate_limit.backend = RateLimitBackend::Postgres;
+    })
+    .await
+    .context("spawn testing app")?;
+    let replica = start_app(app.settings.clone())
+        .await
+        .context("spawn replica")?;
+
+    assert_eq!(get(&app, "/v1/cats").await?.status(), StatusCode::OK);
+    assert_eq!(get(&app, "/v1/cats").await?.status(), StatusCode::OK);
+    let resp = get(&replica, "/v1/cats").await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(header(&resp, "ratelimit-remaining"), Some("0"));
+
+    for app in [&app, &replica] {
+        let resp = get(app, "/v1/cats").await?;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(header(&resp, RETRY_AFTER.as_str()), Some("100"));
+    }
+
+    let buckets: i64 = sqlx::query_scalar("SELECT count(*) FROM rate_limit_buckets")
+        .fetch_one(&app.db_pool)
+        .await?;
+    assert_eq!(buckets, 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_rate_limiting_is_off_by_default() -> Result<()> {
+    // behind a load balancer without trusted_proxies set every client would
+    // share the balancer's bucket, so it has to be asked for
+    let app = spawn_app_with(|c| c.rate_limit.cats = slow(1))
+        .await
+        .context("spawn testing app")?;
+
+    for _ in 0..3 {
+        let resp = get(&app, "/v1/cats").await?;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(header(&resp, "ratelimit-limit"), None);
+    }
+
+    Ok(())
+}
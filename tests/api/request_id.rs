@@ -0,0 +1,89 @@
+use crate::utils::spawn_app;
+use anyhow::Context;
+use anyhow::Result;
+use reqwest::StatusCode;
+use serde_json::Value;
+use uuid::Uuid;
+
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+#[tokio::test]
+pub async fn test_request_id_echoed() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    let endpoint = format!("{}/health", app.address);
+    let resp = app
+        .api_client
+        .get(endpoint)
+        .header(REQUEST_ID_HEADER, "abc-123")
+        .send()
+        .await
+        .context("send request")?;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers()
+            .get(REQUEST_ID_HEADER)
+            .context("request id header")?,
+        "abc-123"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_request_id_generated() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    // none given, and one too long to be trusted
+    let cases = vec![(None, "No Header"), (Some("x".repeat(129)), "Long Header")];
+
+    for (given, msg) in cases {
+        let endpoint = format!("{}/health", app.address);
+        let mut req = app.api_client.get(endpoint);
+        if let Some(given) = given {
+            req = req.header(REQUEST_ID_HEADER, given);
+        }
+        let resp = req.send().await.context("send request")?;
+
+        let id = resp
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .context("request id header")?
+            .to_str()?;
+        let id = Uuid::parse_str(id).context(msg)?;
+        assert_eq!(id.get_version_num(), 7, "{msg}");
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_request_id_in_error_body() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    let endpoint = format!("{}/v1/cats/{}", app.address, Uuid::new_v4());
+    let resp = app
+        .api_client
+        .get(endpoint)
+        .send()
+        .await
+        .context("send request")?;
+
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    // the generated id should be the same one the body reports
+    let header = resp
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .context("request id header")?
+        .to_str()?
+        .to_string();
+    let problem: Value = resp.json().await?;
+    assert_eq!(problem["request_id"], header);
+
+    Ok(())
+}
@@ -0,0 +1,134 @@
+use crate::utils::{spawn_app, spawn_app_with};
+use anyhow::Context;
+use anyhow::Result;
+use axum::http::StatusCode;
+use serde_json::Value;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+
+#[tokio::test]
+pub async fn test_ready_fails_once_shutdown_begins() -> Result<()> {
+    // keep serving for a while after shutdown starts so we can ask
+    let app = spawn_app_with(|c| c.application.shutdown_delay_ms = 5000)
+        .await
+        .context("spawn testing app")?;
+
+    app.shutdown.trigger();
+
+    // send the request
+    let endpoint = format!("{}/health/ready", app.address);
+    let resp = app
+        .api_client
+        .get(endpoint)
+        .send()
+        .await
+        .context("send request")?;
+
+    // check status
+    assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    let report: Value = resp.json().await?;
+    assert_eq!(report["status"], "fail");
+    let check = report["checks"]
+        .as_array()
+        .context("checks")?
+        .iter()
+        .find(|check| check["name"] == "shutdown")
+        .context("shutdown check")?;
+    assert_eq!(check["status"], "fail");
+    assert_eq!(check["detail"], "shutting down");
+
+    // liveness is unaffected
+    let endpoint = format!("{}/health/live", app.address);
+    let resp = app.api_client.get(endpoint).send().await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_shutdown_finishes_in_flight_requests() -> Result<()> {
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    // start a slow request, then shut down underneath it
+    let endpoint = format!("{}/latency", app.address);
+    let request = tokio::spawn(app.api_client.get(endpoint).send());
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    app.shutdown.trigger();
+
+    let resp = request.await?.context("send request")?;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // the server stops once it's drained
+    tokio::time::timeout(Duration::from_secs(5), app.server)
+        .await
+        .context("server should stop")???;
+
+    // and doesn't take new connections
+    let endpoint = format!("{}/health", app.address);
+    assert!(app.api_client.get(endpoint).send().await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_drain_timeout_closes_stuck_connections() -> Result<()> {
+    // spawn our app
+    let app = spawn_app_with(|c| c.application.drain_timeout_ms = 200)
+        .await
+        .context("spawn testing app")?;
+
+    // a request whose body never finishes arriving
+    let addr = app.address.trim_start_matches("http://");
+    let mut stream = TcpStream::connect(addr).await?;
+    stream
+        .write_all(
+            b"POST /v1/cats HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: 100\r\n\r\n{",
+        )
+        .await?;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    app.shutdown.trigger();
+
+    // the server gives up on it instead of waiting forever
+    tokio::time::timeout(Duration::from_secs(5), app.server)
+        .await
+        .context("server should stop after the drain timeout")???;
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_drain_timeout_cuts_off_in_flight_requests() -> Result<()> {
+    // spawn our app
+    let app = spawn_app_with(|c| c.application.drain_timeout_ms = 50)
+        .await
+        .context("spawn testing app")?;
+
+    // /latency takes up to a second, so some of these will still be running
+    // once the deadline passes
+    let endpoint = format!("{}/latency", app.address);
+    let requests: Vec<_> = (0..20)
+        .map(|_| tokio::spawn(app.api_client.get(&endpoint).send()))
+        .collect();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    app.shutdown.trigger();
+
+    tokio::time::timeout(Duration::from_secs(5), app.server)
+        .await
+        .context("server should stop after the drain timeout")???;
+
+    // those are cut off, rather than left running to fail on a closed pool
+    let mut cut_off = 0;
+    for request in requests {
+        match request.await? {
+            Ok(resp) => assert_eq!(resp.status(), StatusCode::OK),
+            Err(_) => cut_off += 1,
+        }
+    }
+    assert!(cut_off > 0, "every request finished before the deadline");
+
+    Ok(())
+}
@@ -0,0 +1,167 @@
+use crate::utils::spawn_app_with;
+use anyhow::Context;
+use anyhow::Result;
+use rcgen::{BasicConstraints, CertificateParams, CertifiedIssuer, IsCa, KeyPair};
+use reqwest::{StatusCode, Version};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// A throwaway CA, and a cert for localhost signed by it
+struct TestPki {
+    ca_pem: String,
+    cert_pem: String,
+    key_pem: String,
+}
+
+impl TestPki {
+    fn generate() -> Result<Self> {
+        let mut ca_params = CertificateParams::new(Vec::<String>::new())?;
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = CertifiedIssuer::self_signed(ca_params, KeyPair::generate()?)?;
+
+        let key = KeyPair::generate()?;
+        let cert = CertificateParams::new(vec!["localhost".to_string()])?.signed_by(&key, &ca)?;
+
+        Ok(Self {
+            ca_pem: ca.pem(),
+            cert_pem: cert.pem(),
+            key_pem: key.serialize_pem(),
+        })
+    }
+
+    fn write(&self, dir: &Path) -> Result<()> {
+        std::fs::write(dir.join("cert.pem"), &self.cert_pem)?;
+        std::fs::write(dir.join("key.pem"), &self.key_pem)?;
+        Ok(())
+    }
+
+    /// A fresh client trusting only this CA, so nothing is reused between calls
+    fn client(&self) -> Result<reqwest::Client> {
+        let client = reqwest::Client::builder()
+            .tls_built_in_root_certs(false)
+            .add_root_certificate(reqwest::Certificate::from_pem(self.ca_pem.as_bytes())?)
+            .build()?;
+        Ok(client)
+    }
+}
+
+fn cert_dir() -> Result<PathBuf> {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(Uuid::new_v4().to_string());
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+#[tokio::test]
+pub async fn test_https_with_alpn() -> Result<()> {
+    let pki = TestPki::generate()?;
+    let dir = cert_dir()?;
+    pki.write(&dir)?;
+
+    let app = spawn_app_with(|c| {
+        c.application.tls.enabled = true;
+        c.application.tls.cert_path = dir.join("cert.pem");
+        c.application.tls.key_path = dir.join("key.pem");
+    })
+    .await
+    .context("spawn testing app")?;
+    assert!(app.address.starts_with("https://"));
+
+    // http/2 when the client offers it
+    let resp = pki
+        .client()?
+        .get(format!("{}/health", app.address))
+        .send()
+        .await
+        .context("send request")?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.version(), Version::HTTP_2);
+
+    // http/1.1 otherwise
+    let http1 = reqwest::Client::builder()
+        .tls_built_in_root_certs(false)
+        .add_root_certificate(reqwest::Certificate::from_pem(pki.ca_pem.as_bytes())?)
+        .http1_only()
+        .build()?;
+    let resp = http1
+        .get(format!("{}/health", app.address))
+        .send()
+        .await
+        .context("send request")?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.version(), Version::HTTP_11);
+
+    // and no plain http
+    let plain = app.address.replacen("https://", "http://", 1);
+    assert!(
+        app.api_client
+            .get(format!("{plain}/health"))
+            .send()
+            .await
+            .is_err()
+    );
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_certificate_hot_reload() -> Result<()> {
+    let old = TestPki::generate()?;
+    let new = TestPki::generate()?;
+    let dir = cert_dir()?;
+    old.write(&dir)?;
+
+    let app = spawn_app_with(|c| {
+        c.application.tls.enabled = true;
+        c.application.tls.cert_path = dir.join("cert.pem");
+        c.application.tls.key_path = dir.join("key.pem");
+        c.application.tls.reload_interval_ms = 50;
+    })
+    .await
+    .context("spawn testing app")?;
+    let endpoint = format!("{}/health", app.address);
+
+    assert!(old.client()?.get(&endpoint).send().await.is_ok());
+    assert!(new.client()?.get(&endpoint).send().await.is_err());
+
+    // a broken cert is ignored, the old one keeps working
+    std::fs::write(dir.join("cert.pem"), "not a certificate")?;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(old.client()?.get(&endpoint).send().await.is_ok());
+
+    // the renewed cert is picked up without a restart
+    new.write(&dir)?;
+    let started = Instant::now();
+    while new.client()?.get(&endpoint).send().await.is_err() {
+        assert!(
+            started.elapsed() < Duration::from_secs(5),
+            "new certificate never served"
+        );
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert!(old.client()?.get(&endpoint).send().await.is_err());
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_mismatched_key_is_rejected() -> Result<()> {
+    let one = TestPki::generate()?;
+    let other = TestPki::generate()?;
+    let dir = cert_dir()?;
+    std::fs::write(dir.join("cert.pem"), &one.cert_pem)?;
+    std::fs::write(dir.join("key.pem"), &other.key_pem)?;
+
+    let result = spawn_app_with(|c| {
+        c.application.tls.enabled = true;
+        c.application.tls.cert_path = dir.join("cert.pem");
+        c.application.tls.key_path = dir.join("key.pem");
+    })
+    .await;
+    assert!(result.is_err());
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
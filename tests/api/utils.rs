@@ -1,40 +1,66 @@
 use anyhow::Context;
 use anyhow::Result;
-use gha_demo::App;
-use gha_demo::settings::{DbSettings, get_settings};
+use gha_demo::api_keys::ApiKey;
+use gha_demo::auth::Scope;
+use gha_demo::settings::{DbSettings, Settings, TEST_ENVIRONMENT, get_settings_from};
 use gha_demo::types::v1::types::Cat;
 use gha_demo::types::v1::types::EyeColor;
+use gha_demo::{App, Shutdown};
+use reqwest::header::{AUTHORIZATION, HeaderMap, HeaderValue};
 use secrecy::SecretString;
 use sqlx::Connection;
 use sqlx::Executor;
 use sqlx::PgConnection;
 use sqlx::PgPool;
+use std::process::{Child, Command, Stdio};
 use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
 use uuid::Uuid;
 
 static TRACING: LazyLock<()> = LazyLock::new(|| {
     if std::env::var("TESTING_LOG").is_ok() {
-        tracing_subscriber::fmt::init();
+        // another test binary may have installed its own subscriber already
+        let _ = tracing_subscriber::fmt().try_init();
     }
 });
 
+/// Settings from the test environment, the one allowing port 0
+pub fn test_settings() -> Result<Settings> {
+    get_settings_from(None, Some(TEST_ENVIRONMENT)).context("read settings for test")
+}
+
 pub struct TestApp {
+    /// what the app was built with, the db name included
+    pub settings: Settings,
     pub address: String,
+    pub metrics_address: String,
     pub db_pool: PgPool,
+    /// already sent by `api_client`, with every scope
+    pub api_key: String,
     pub api_client: reqwest::Client,
+    pub shutdown: Shutdown,
+    pub server: JoinHandle<Result<()>>,
 }
 
 pub async fn spawn_app() -> Result<TestApp> {
+    spawn_app_with(|_| {}).await
+}
+
+/// Like [`spawn_app`], with a chance to tweak the settings first
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Settings)) -> Result<TestApp> {
     // initialize tracing for our tests
     LazyLock::force(&TRACING);
 
     // Randomise configuration to ensure test isolation
     let configuration = {
-        let mut c = get_settings().context("read settings for test")?;
+        let mut c = test_settings()?;
         // Use a different database for each test case
         c.db.database = Uuid::new_v4().to_string();
         // Use a random OS port
         c.application.port = 0;
+        c.metrics.port = 0;
+        configure(&mut c);
 
         c
     };
@@ -44,6 +70,14 @@ pub async fn spawn_app() -> Result<TestApp> {
         .await
         .context("configure db")?;
 
+    start_app(configuration).await
+}
+
+/// Runs another app with `configuration` as is, say to share a db with one
+/// that's already running
+pub async fn start_app(configuration: Settings) -> Result<TestApp> {
+    LazyLock::force(&TRACING);
+
     // Launch the application as a background task
     let application = App::build(configuration.clone())
         .await
@@ -53,33 +87,78 @@ pub async fn spawn_app() -> Result<TestApp> {
     let port = application
         .port()
         .context("get application port for test")?;
-    let address = format!("http://localhost:{}", port);
+    let scheme = if configuration.application.tls.enabled {
+        "https"
+    } else {
+        "http"
+    };
+    let address = format!("{scheme}://localhost:{port}");
+    let metrics_port = application
+        .metrics_port()
+        .context("get metrics port for test")?
+        .context("metrics should be enabled in tests")?;
+    let metrics_address = format!("http://localhost:{}", metrics_port);
 
     // spawn our app as a background task
-    let _ = tokio::spawn(application.run_until_stopped());
-
-    // create our request client
-    let api_client = reqwest::Client::builder()
-        .redirect(reqwest::redirect::Policy::none())
-        .build()
-        .context("build http client")?;
+    let shutdown = application.shutdown_handle();
+    let server = tokio::spawn(async move {
+        application
+            .run_until_stopped()
+            .await
+            .context("run app in test")
+    });
 
     // create the connection to our database
     let db_pool = PgPool::connect_with(configuration.db.get_db_settings())
         .await
         .context("connect to db")?;
 
+    // create our request client, allowed to do anything
+    let (_, api_key) = ApiKey::mint(&db_pool, "tests", &Scope::ALL)
+        .await
+        .context("mint api key")?;
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        AUTHORIZATION,
+        HeaderValue::from_str(&format!("Bearer {api_key}"))?,
+    );
+    let api_client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .default_headers(headers)
+        .build()
+        .context("build http client")?;
+
     let test_app = TestApp {
+        settings: configuration,
         db_pool,
         address,
+        metrics_address,
+        api_key,
         api_client,
+        shutdown,
+        server,
     };
 
     Ok(test_app)
 }
 
-async fn configure_db(settings: &DbSettings) -> Result<()> {
-    // Create database
+pub async fn configure_db(settings: &DbSettings) -> Result<()> {
+    create_db(settings).await?;
+
+    // Migrate database
+    let connection_pool = PgPool::connect_with(settings.get_db_settings())
+        .await
+        .context("migrate test db")?;
+
+    sqlx::migrate!("./migrations")
+        .run(&connection_pool)
+        .await
+        .expect("Failed to migrate the database");
+    Ok(())
+}
+
+/// Creates the database without migrating it
+pub async fn create_db(settings: &DbSettings) -> Result<()> {
     let maintenance_settings = DbSettings {
         database: "postgres".to_string(),
         username: "postgres".to_string(),
@@ -98,18 +177,50 @@ async fn configure_db(settings: &DbSettings) -> Result<()> {
         .await
         .context("create test db")?;
 
-    // Migrate database
-    let connection_pool = PgPool::connect_with(settings.get_db_settings())
-        .await
-        .context("migrate test db")?;
-
-    sqlx::migrate!("./migrations")
-        .run(&connection_pool)
-        .await
-        .expect("Failed to migrate the database");
     Ok(())
 }
 
+/// Settings pointing at a brand new database, so the binary's migrations
+/// don't step on anyone
+pub async fn fresh_db_settings(migrated: bool) -> Result<DbSettings> {
+    let mut settings = test_settings()?.db;
+    settings.database = Uuid::new_v4().to_string();
+    if migrated {
+        configure_db(&settings).await?;
+    } else {
+        create_db(&settings).await?;
+    }
+
+    Ok(settings)
+}
+
+/// The real binary, with settings tweaked through the environment
+pub fn binary(env: &[(&str, &str)]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_gha_demo"));
+    command
+        .env("APP_ENV", TEST_ENVIRONMENT)
+        .env("APP_APPLICATION__PORT", "0")
+        .env("APP_METRICS__PORT", "0")
+        .envs(env.iter().copied())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    command
+}
+
+pub fn wait_with_timeout(child: &mut Child, timeout: Duration) -> Result<i32> {
+    let started = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return status.code().context("exited by signal");
+        }
+        if started.elapsed() > timeout {
+            child.kill()?;
+            anyhow::bail!("process didn't exit within {timeout:?}");
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
 pub async fn create_two_cats(pool: &sqlx::PgPool) -> Result<[Cat; 2]> {
     // Example data
     let cat1 = Cat {
@@ -127,9 +238,31 @@ pub async fn create_two_cats(pool: &sqlx::PgPool) -> Result<[Cat; 2]> {
     };
 
     // Create both cats
-    let _ = cat1.write_to_db(pool).await?;
+    cat1.write_to_db(pool).await?;
 
-    let _ = cat2.write_to_db(pool).await?;
+    cat2.write_to_db(pool).await?;
 
     Ok([cat1, cat2])
 }
+
+pub async fn create_n_cats(pool: &sqlx::PgPool, n: usize) -> Result<Vec<Cat>> {
+    let mut cats = Vec::with_capacity(n);
+
+    for i in 0..n {
+        let cat = Cat {
+            name: format!("Cat {i}"),
+            cool_cat_club_id: Uuid::new_v4(),
+            age: (i % 20) as i16,
+            eye_color: if i % 2 == 0 {
+                EyeColor::Blue
+            } else {
+                EyeColor::Brown
+            },
+        };
+
+        cat.write_to_db(pool).await?;
+        cats.push(cat);
+    }
+
+    Ok(cats)
+}
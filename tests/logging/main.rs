@@ -0,0 +1,106 @@
+// separate binary from tests/api since installing the global subscriber can
+// only happen once per process
+#[path = "../api/utils.rs"]
+#[allow(dead_code)]
+mod utils;
+
+use anyhow::Context;
+use anyhow::Result;
+use gha_demo::settings::{LogFormat, LoggingSettings, OtlpProtocol, OtlpSettings};
+use gha_demo::telemetry::{get_subscriber, get_subscriber_with_writer, init_subscriber};
+use reqwest::StatusCode;
+use serde_json::Value;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use utils::spawn_app;
+
+/// Collects everything logged so the test can read it back
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().expect("lock captured").write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Captured {
+    fn lines(&self) -> Vec<Value> {
+        let buf = self.0.lock().expect("lock captured");
+
+        String::from_utf8_lossy(&buf)
+            .lines()
+            .filter_map(|l| serde_json::from_str(l).ok())
+            .collect()
+    }
+}
+
+fn otlp_disabled() -> OtlpSettings {
+    OtlpSettings {
+        enabled: false,
+        endpoint: String::new(),
+        protocol: OtlpProtocol::Grpc,
+        service_name: String::new(),
+        sampling_ratio: 1.0,
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+pub async fn test_json_request_logs() -> Result<()> {
+    // install json logging into our buffer
+    let captured = Captured::default();
+    let logging = LoggingSettings {
+        format: LogFormat::Json,
+    };
+    let writer = captured.clone();
+    let (subscriber, _guard) =
+        get_subscriber_with_writer(&logging, &otlp_disabled(), move || writer.clone())?;
+    init_subscriber(subscriber)?;
+
+    // a second install should be refused, not panic
+    let (subscriber, _guard) = get_subscriber(&logging, &otlp_disabled())?;
+    assert!(init_subscriber(subscriber).is_err());
+
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    let endpoint = format!("{}/v1/cats", app.address);
+    let resp = app
+        .api_client
+        .get(endpoint)
+        .header("X-Request-Id", "logging-test")
+        .send()
+        .await
+        .context("send request")?;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // the span closes just after the response goes out, give it a moment
+    let mut access_log = None;
+    for _ in 0..50 {
+        access_log = captured.lines().into_iter().find(|l| {
+            l["msg"] == "[REQUEST - EVENT] finished processing request"
+                && l["request_id"] == "logging-test"
+        });
+        if access_log.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    let access_log = access_log.context("access log line never written")?;
+    assert_eq!(access_log["route"], "/v1/cats");
+    assert_eq!(access_log["status"], 200);
+    assert!(access_log["latency_ms"].is_u64());
+
+    // bunyan's core fields
+    assert_eq!(access_log["v"], 0);
+    assert_eq!(access_log["name"], "gha_demo");
+    assert!(access_log["hostname"].is_string());
+
+    Ok(())
+}
@@ -0,0 +1,116 @@
+// separate binary from tests/api since installing the global subscriber can
+// only happen once per process
+#[path = "../api/utils.rs"]
+#[allow(dead_code)]
+mod utils;
+
+use anyhow::Context;
+use anyhow::Result;
+use axum::Router;
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::routing::post;
+use gha_demo::settings::{LogFormat, LoggingSettings, OtlpProtocol, OtlpSettings};
+use gha_demo::telemetry::{get_subscriber, init_subscriber};
+use reqwest::StatusCode;
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use utils::spawn_app;
+
+type Received = Arc<Mutex<Vec<Value>>>;
+
+/// Stands in for an OpenTelemetry collector speaking OTLP over http/json,
+/// keeping every export request it gets
+async fn spawn_collector() -> Result<(String, Received)> {
+    let received = Received::default();
+
+    let router = Router::new()
+        .route(
+            "/v1/traces",
+            post(|State(received): State<Received>, body: Bytes| async move {
+                if let Ok(json) = serde_json::from_slice::<Value>(&body) {
+                    received.lock().expect("lock received").push(json);
+                }
+                "{}"
+            }),
+        )
+        .with_state(received.clone());
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .context("bind collector")?;
+    let address = format!("http://{}", listener.local_addr()?);
+    drop(tokio::spawn(
+        async move { axum::serve(listener, router).await },
+    ));
+
+    Ok((address, received))
+}
+
+/// Every span in every export request, flattened
+fn exported_spans(received: &Received) -> Vec<Value> {
+    let received = received.lock().expect("lock received");
+
+    received
+        .iter()
+        .flat_map(|r| r["resourceSpans"].as_array().cloned().unwrap_or_default())
+        .flat_map(|r| r["scopeSpans"].as_array().cloned().unwrap_or_default())
+        .flat_map(|s| s["spans"].as_array().cloned().unwrap_or_default())
+        .collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+pub async fn test_request_spans_exported_with_parent() -> Result<()> {
+    let (collector, received) = spawn_collector().await?;
+
+    // install tracing pointed at our stand-in
+    let otlp = OtlpSettings {
+        enabled: true,
+        endpoint: collector,
+        protocol: OtlpProtocol::HttpJson,
+        service_name: "gha_demo_test".to_string(),
+        sampling_ratio: 0.0,
+    };
+    let logging = LoggingSettings {
+        format: LogFormat::Compact,
+    };
+    let (subscriber, guard) = get_subscriber(&logging, &otlp)?;
+    init_subscriber(subscriber)?;
+
+    // spawn our app
+    let app = spawn_app().await.context("spawn testing app")?;
+
+    // continue a trace started by some upstream caller, the sampled flag should
+    // win over our 0 sampling ratio
+    let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+    let parent_span_id = "00f067aa0ba902b7";
+    let endpoint = format!("{}/health", app.address);
+    let resp = app
+        .api_client
+        .get(endpoint)
+        .header("traceparent", format!("00-{trace_id}-{parent_span_id}-01"))
+        .send()
+        .await
+        .context("send request")?;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // the span closes just after the response goes out, give it a moment
+    let mut request_span = None;
+    for _ in 0..50 {
+        guard.force_flush()?;
+        request_span = exported_spans(&received)
+            .into_iter()
+            .find(|s| s["name"] == "GET /health");
+        if request_span.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    let request_span = request_span.context("request span never exported")?;
+    assert_eq!(request_span["traceId"], trace_id);
+    assert_eq!(request_span["parentSpanId"], parent_span_id);
+
+    Ok(())
+}